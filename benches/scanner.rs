@@ -0,0 +1,95 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use wrig::components::{Scanner, TextEdit};
+
+/// A chunk of script exercising every kind of token the scanner knows about.
+const CHUNK: &str = r#"// A generated chunk of script
+var total_count = 0x1F + 0b1010 * 1_000.5e-3;
+/* block comments /* can nest */ too */
+fun greet(name) {
+    print "Hello ${name}, you have ${total_count + 1} items\n";
+    while (total_count >= 10 and name != nil) { total_count = total_count - 1; }
+}
+"#;
+
+/// Repeats the chunk until the source is at least `size` bytes long.
+fn generate_source(size: usize) -> String {
+    CHUNK.repeat(size / CHUNK.len() + 1)
+}
+
+fn scan_tokens(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan_tokens");
+
+    group.sample_size(10);
+
+    for megabytes in [1, 4, 16] {
+        let source = generate_source(megabytes * 1024 * 1024);
+
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{megabytes}MiB")),
+            &source,
+            |b, source| {
+                b.iter(|| {
+                    let mut scanner = Scanner::new(black_box(source));
+
+                    scanner.scan_tokens().0.len()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Typing a character in the middle of a 20k line file, compared to scanning the whole file again. The file is also
+/// tried with an invalid escape on its first line, which shouldn't stop the re-scan from starting close to the edit.
+fn relex(c: &mut Criterion) {
+    let mut group = c.benchmark_group("relex");
+
+    let source = CHUNK.repeat(20_000 / CHUNK.lines().count());
+    let sources = [
+        ("", source.clone()),
+        ("_after_invalid_escape", format!("print \"\\q\";\n{source}")),
+    ];
+
+    for (suffix, source) in sources {
+        let middle = source.len() / 2;
+        let position = middle + source[middle..].find("total_count").unwrap();
+
+        let edit = TextEdit {
+            range: position..position,
+            text: "a",
+        };
+        let edited = format!("{}a{}", &source[..position], &source[position..]);
+
+        let mut old_scanner = Scanner::new(&source);
+        old_scanner.scan_tokens();
+        let (old_tokens, old_errors) = old_scanner.into_parts();
+
+        group.bench_function(format!("full{suffix}"), |b| {
+            b.iter(|| {
+                let mut scanner = Scanner::new(black_box(&edited));
+
+                scanner.scan_tokens().0.len()
+            })
+        });
+        group.bench_function(format!("incremental{suffix}"), |b| {
+            b.iter_batched(
+                || (old_tokens.clone(), old_errors.clone()),
+                |(old_tokens, old_errors)| {
+                    let mut scanner = Scanner::new(black_box(&edited));
+
+                    scanner.relex(old_tokens, old_errors, &edit).0.len()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, scan_tokens, relex);
+criterion_main!(benches);
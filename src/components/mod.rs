@@ -1,8 +1,10 @@
+pub use parser::*;
 pub use program::*;
-use scanner::*;
+pub use scanner::*;
+
+pub mod parser_components;
+pub mod token_components;
 
 mod parser;
-mod parser_components;
 mod program;
 mod scanner;
-mod token_components;
@@ -1,50 +1,436 @@
+use std::{collections::VecDeque, mem};
+
 use thiserror::Error;
 
 use super::{
-    parser_components::Expr,
+    parser_components::{Expr, Stmt},
+    scanner::ScannerError,
     token_components::{
-        LiteralType, Token,
+        LiteralType, Span, Token,
         TokenType::{self, *},
     },
 };
 
 #[derive(Error, Debug)]
 pub enum ParserError {
-    #[error("Unexpected token '{found}', expected {expected} on line {line}")]
-    UnexpectedToken {
-        found: Token,
-        expected: Token,
-        line: usize,
-    },
-    #[error("Parse error. {message}")]
-    ParseError { message: String },
-    #[error("No primary found on line {line}")]
-    PrimaryError { line: usize },
-    #[error("No literal type found on line {line}")]
-    UndefinedLiteral { line: usize },
+    #[error("Parse error on {span}. {message}")]
+    ParseError { message: String, span: Span },
+    #[error("No primary found on {span}")]
+    PrimaryError { span: Span },
+    #[error("No literal type found on {span}")]
+    UndefinedLiteral { span: Span },
+    #[error("Invalid assignment target on {span}. Only variables can be assigned to")]
+    InvalidAssignmentTarget { span: Span },
+    #[error(transparent)]
+    ScannerError(#[from] ScannerError),
 }
 
-pub struct Parser<'a> {
-    tokens: &'a Vec<Token>,
-    current: usize,
+/// The most arguments a call can pass, and the most parameters a function can declare.
+const MAX_ARGUMENTS: usize = 255;
+
+/// Parses a stream of tokens, pulling them from the stream only as they are needed.
+pub struct Parser<'src, I>
+where
+    I: Iterator<Item = Result<Token<'src>, ScannerError>>,
+{
+    tokens: I,
+    /// Tokens pulled from the stream that have not been consumed yet. The front is the current token.
+    lookahead: VecDeque<Token<'src>>,
+    previous: Option<Token<'src>>,
+    /// The error that ended the stream early, such as an unterminated string. Nothing more is pulled after it.
+    scanner_error: Option<ScannerError>,
+    errors: Vec<ParserError>,
+    /// How many loops the statement being parsed is inside of, within the innermost function.
+    loop_depth: usize,
 }
 
-trait ASTOperations {
-    fn expression(&mut self) -> Result<Expr, ParserError>;
-    fn equality(&mut self) -> Result<Expr, ParserError>;
-    fn comparison(&mut self) -> Result<Expr, ParserError>;
-    fn term(&mut self) -> Result<Expr, ParserError>;
-    fn factor(&mut self) -> Result<Expr, ParserError>;
-    fn unary(&mut self) -> Result<Expr, ParserError>;
-    fn primary(&mut self) -> Result<Expr, ParserError>;
+trait ASTOperations<'src> {
+    fn declaration(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn class_declaration(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn function(&mut self, kind: &str) -> Result<Stmt<'src>, ParserError>;
+    fn var_declaration(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn if_statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn while_statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn for_statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn return_statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn loop_control_statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn print_statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn expression_statement(&mut self) -> Result<Stmt<'src>, ParserError>;
+    fn block(&mut self) -> Result<Vec<Stmt<'src>>, ParserError>;
+    fn expression(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn comma(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn assignment(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn conditional(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn logic_or(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn logic_and(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn equality(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn comparison(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn term(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn factor(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn unary(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn call(&mut self) -> Result<Expr<'src>, ParserError>;
+    fn primary(&mut self) -> Result<Expr<'src>, ParserError>;
 }
 
-impl<'a> ASTOperations for Parser<'a> {
-    fn expression(&mut self) -> Result<Expr, ParserError> {
-        self.equality()
+impl<'src, I> ASTOperations<'src> for Parser<'src, I>
+where
+    I: Iterator<Item = Result<Token<'src>, ScannerError>>,
+{
+    fn declaration(&mut self) -> Result<Stmt<'src>, ParserError> {
+        if self.match_token_type(&[Class]) {
+            return self.class_declaration();
+        }
+
+        // Without a name, `fun` starts a lambda in an expression statement.
+        if self.current_eq(Fun) && self.peek_ahead(1).token == Identifier {
+            self.advance();
+
+            return self.function("function");
+        }
+
+        if self.match_token_type(&[Var]) {
+            return self.var_declaration();
+        }
+
+        self.statement()
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt<'src>, ParserError> {
+        let doc = self.previous().doc_comment();
+        let name = self
+            .consume(Identifier, "Expected class name".into())?
+            .clone();
+
+        let superclass = if self.match_token_type(&[Less]) {
+            let superclass = self
+                .consume(Identifier, "Expected superclass name".into())?
+                .clone();
+
+            // Nothing stops parsing here, so the error is recorded and the class parsed as normal.
+            if superclass.lexeme == name.lexeme {
+                self.errors.push(ParserError::ParseError {
+                    message: "A class can't inherit from itself".into(),
+                    span: superclass.span,
+                });
+            }
+
+            Some(Expr::Variable(superclass))
+        } else {
+            None
+        };
+
+        self.consume(LeftBrace, "Expected '{' before class body".into())?;
+
+        let mut methods = vec![];
+
+        while !self.current_eq(RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(RightBrace, "Expected '}' after class body".into())?;
+
+        Ok(Stmt::Class(name, superclass, methods, doc))
+    }
+
+    /// Parses a function's name, parameters and body. `kind` names what is being declared in error messages.
+    fn function(&mut self, kind: &str) -> Result<Stmt<'src>, ParserError> {
+        // Methods have no `fun` keyword, so their doc comment is on the name instead.
+        let doc = match self.previous().token {
+            Fun => self.previous().doc_comment(),
+            _ => self.peek().doc_comment(),
+        };
+        let name = self
+            .consume(Identifier, format!("Expected {kind} name"))?
+            .clone();
+
+        self.consume(LeftParen, format!("Expected '(' after {kind} name"))?;
+
+        let params = self.parameters()?;
+
+        self.consume(LeftBrace, format!("Expected '{{' before {kind} body"))?;
+
+        let body = self.function_body()?;
+
+        Ok(Stmt::Function(name, params, body, doc))
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt<'src>, ParserError> {
+        let doc = self.previous().doc_comment();
+        let name = self
+            .consume(Identifier, "Expected variable name".into())?
+            .clone();
+
+        let initialiser = if self.match_token_type(&[Equal]) {
+            Some(self.assignment()?)
+        } else {
+            None
+        };
+
+        self.consume(Semicolon, "Expected ';' after variable declaration".into())?;
+
+        Ok(Stmt::Var(name, initialiser, doc))
+    }
+
+    fn statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        if self.match_token_type(&[If]) {
+            return self.if_statement();
+        }
+
+        if self.match_token_type(&[While]) {
+            return self.while_statement();
+        }
+
+        if self.match_token_type(&[For]) {
+            return self.for_statement();
+        }
+
+        if self.match_token_type(&[Print]) {
+            return self.print_statement();
+        }
+
+        if self.match_token_type(&[Return]) {
+            return self.return_statement();
+        }
+
+        if self.match_token_type(&[Break, Continue]) {
+            return self.loop_control_statement();
+        }
+
+        if self.current_eq(LeftBrace) && !self.is_at_map_literal() {
+            self.advance();
+
+            return Ok(Stmt::Block(self.block()?));
+        }
+
+        self.expression_statement()
+    }
+
+    /// An `else` belongs to the closest `if` before it, so `if (a) if (b) c; else d;` only runs `d` when `b` is false.
+    fn if_statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        self.consume(LeftParen, "Expected '(' after 'if'".into())?;
+        let condition = self.expression()?;
+        self.consume(RightParen, "Expected ')' after if condition".into())?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if self.match_token_type(&[Else]) {
+            Some(Box::from(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If(condition, Box::from(then_branch), else_branch))
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        self.consume(LeftParen, "Expected '(' after 'while'".into())?;
+        let condition = self.expression()?;
+        self.consume(RightParen, "Expected ')' after while condition".into())?;
+
+        let body = self.loop_body()?;
+
+        Ok(Stmt::While(condition, Box::from(body), None))
+    }
+
+    /// There is no for loop in the AST. `for (init; condition; increment) body` becomes
+    /// `{ init; while (condition) body }`, with the increment kept on the while so it still runs after a `continue`.
+    /// A missing condition is `true`.
+    fn for_statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        self.consume(LeftParen, "Expected '(' after 'for'".into())?;
+
+        let initialiser = if self.match_token_type(&[Semicolon]) {
+            None
+        } else if self.match_token_type(&[Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.current_eq(Semicolon) {
+            Expr::Literal(LiteralType::Bool(true))
+        } else {
+            self.expression()?
+        };
+        self.consume(Semicolon, "Expected ';' after loop condition".into())?;
+
+        let increment = if self.current_eq(RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(RightParen, "Expected ')' after for clauses".into())?;
+
+        let body = self.loop_body()?;
+        let mut body = Stmt::While(condition, Box::from(body), increment);
+
+        if let Some(initialiser) = initialiser {
+            body = Stmt::Block(vec![initialiser, body]);
+        }
+
+        Ok(body)
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        let keyword = self.previous().clone();
+
+        let value = if self.current_eq(Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(Semicolon, "Expected ';' after return value".into())?;
+
+        Ok(Stmt::Return(keyword, value))
     }
 
-    fn equality(&mut self) -> Result<Expr, ParserError> {
+    /// `break` or `continue`, which are only allowed in a loop. A function inside a loop is a new context, so they can't
+    /// be used in it until it has a loop of its own.
+    fn loop_control_statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        let keyword = self.previous().clone();
+
+        // The statement is still well formed, so the error is recorded without unwinding to the next statement.
+        if self.loop_depth == 0 {
+            self.errors.push(ParserError::ParseError {
+                message: format!("Can't use '{keyword}' outside of a loop"),
+                span: keyword.span,
+            });
+        }
+
+        self.consume(Semicolon, format!("Expected ';' after '{keyword}'"))?;
+
+        match keyword.token {
+            Break => Ok(Stmt::Break(keyword)),
+            _ => Ok(Stmt::Continue(keyword)),
+        }
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        let expr = self.expression()?;
+
+        self.consume(Semicolon, "Expected ';' after value".into())?;
+
+        Ok(Stmt::Print(expr))
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt<'src>, ParserError> {
+        let expr = self.expression()?;
+
+        self.consume(Semicolon, "Expected ';' after expression".into())?;
+
+        Ok(Stmt::Expression(expr))
+    }
+
+    /// Parses the statements of a block, the `{` having just been matched.
+    fn block(&mut self) -> Result<Vec<Stmt<'src>>, ParserError> {
+        let mut statements = vec![];
+
+        while !self.current_eq(RightBrace) && !self.is_at_end() {
+            statements.extend(self.declaration_or_synchronise());
+        }
+
+        self.consume(RightBrace, "Expected '}' after block".into())?;
+
+        Ok(statements)
+    }
+
+    fn expression(&mut self) -> Result<Expr<'src>, ParserError> {
+        self.comma()
+    }
+
+    /// `a, b` evaluates both and gives `b`. Where commas separate things, like arguments, `assignment` is parsed instead.
+    fn comma(&mut self) -> Result<Expr<'src>, ParserError> {
+        let mut expr = self.assignment()?;
+
+        while self.match_token_type(&[Comma]) {
+            let op = self.previous().clone();
+            let right_expr = self.assignment()?;
+
+            expr = Expr::Binary(Box::from(expr), op, Box::from(right_expr));
+        }
+
+        Ok(expr)
+    }
+
+    /// Assignment is right-associative, `a = b = c` assigns `c` to `b` and then to `a`.
+    fn assignment(&mut self) -> Result<Expr<'src>, ParserError> {
+        let expr = self.conditional()?;
+
+        if !self.match_token_type(&[Equal]) {
+            return Ok(expr);
+        }
+
+        let equals_span = self.previous().span;
+        let value = self.assignment()?;
+
+        match expr {
+            Expr::Variable(name) => Ok(Expr::Assign(name, Box::from(value))),
+            Expr::Get(object, name) => Ok(Expr::Set(object, name, Box::from(value))),
+            Expr::Index(object, bracket, index) => {
+                Ok(Expr::SetIndex(object, bracket, index, Box::from(value)))
+            }
+            // The parser isn't confused here, so the error is recorded without unwinding to the next statement.
+            expr => {
+                self.errors
+                    .push(ParserError::InvalidAssignmentTarget { span: equals_span });
+
+                Ok(expr)
+            }
+        }
+    }
+
+    /// `a ? b : c ? d : e` is right-associative, it groups as `a ? b : (c ? d : e)`.
+    fn conditional(&mut self) -> Result<Expr<'src>, ParserError> {
+        let condition = self.logic_or()?;
+
+        if !self.match_token_type(&[Question]) {
+            return Ok(condition);
+        }
+
+        let question_span = self.previous().span;
+        let then_branch = self.expression()?;
+
+        self.consume(
+            Colon,
+            format!("Expected ':' to match the '?' on {question_span}"),
+        )?;
+
+        let else_branch = self.conditional()?;
+
+        Ok(Expr::Conditional(
+            Box::from(condition),
+            Box::from(then_branch),
+            Box::from(else_branch),
+        ))
+    }
+
+    fn logic_or(&mut self) -> Result<Expr<'src>, ParserError> {
+        let mut expr = self.logic_and()?;
+
+        while self.match_token_type(&[Or]) {
+            let op = self.previous().clone();
+            let right_expr = self.logic_and()?;
+
+            expr = Expr::Logical(Box::from(expr), op, Box::from(right_expr));
+        }
+
+        Ok(expr)
+    }
+
+    fn logic_and(&mut self) -> Result<Expr<'src>, ParserError> {
+        let mut expr = self.equality()?;
+
+        while self.match_token_type(&[And]) {
+            let op = self.previous().clone();
+            let right_expr = self.equality()?;
+
+            expr = Expr::Logical(Box::from(expr), op, Box::from(right_expr));
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr<'src>, ParserError> {
         let mut expr = self.comparison()?;
         let token_types = [BangEqual, EqualEqual];
 
@@ -58,7 +444,7 @@ impl<'a> ASTOperations for Parser<'a> {
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, ParserError> {
+    fn comparison(&mut self) -> Result<Expr<'src>, ParserError> {
         let mut expr = self.term()?;
         let token_types = [Greater, GreaterEqual, Less, LessEqual];
 
@@ -72,7 +458,7 @@ impl<'a> ASTOperations for Parser<'a> {
         Ok(expr)
     }
 
-    fn term(&mut self) -> Result<Expr, ParserError> {
+    fn term(&mut self) -> Result<Expr<'src>, ParserError> {
         let mut expr = self.factor()?;
         let token_types = [Minus, Plus];
 
@@ -86,7 +472,7 @@ impl<'a> ASTOperations for Parser<'a> {
         Ok(expr)
     }
 
-    fn factor(&mut self) -> Result<Expr, ParserError> {
+    fn factor(&mut self) -> Result<Expr<'src>, ParserError> {
         let mut expr = self.unary()?;
         let token_types = [Slash, Star];
 
@@ -100,7 +486,7 @@ impl<'a> ASTOperations for Parser<'a> {
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, ParserError> {
+    fn unary(&mut self) -> Result<Expr<'src>, ParserError> {
         let token_types = [Bang, Minus];
 
         if self.match_token_type(&token_types) {
@@ -110,10 +496,36 @@ impl<'a> ASTOperations for Parser<'a> {
             return Ok(Expr::Unary(op, Box::from(right_expr)));
         }
 
-        Ok(self.primary()?)
+        self.call()
+    }
+
+    /// Calls, property accesses and indexing can be chained, as in `f(a)(b)` or `a.b()[c]`.
+    fn call(&mut self) -> Result<Expr<'src>, ParserError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token_type(&[LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token_type(&[Dot]) {
+                let name = self
+                    .consume(Identifier, "Expected property name after '.'".into())?
+                    .clone();
+
+                expr = Expr::Get(Box::from(expr), name);
+            } else if self.match_token_type(&[LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self
+                    .consume(RightBracket, "Expected ']' after index".into())?
+                    .clone();
+
+                expr = Expr::Index(Box::from(expr), bracket, Box::from(index));
+            } else {
+                return Ok(expr);
+            }
+        }
     }
 
-    fn primary(&mut self) -> Result<Expr, ParserError> {
+    fn primary(&mut self) -> Result<Expr<'src>, ParserError> {
         if self.match_token_type(&[False]) {
             return Ok(Expr::Literal(LiteralType::Bool(false)));
         }
@@ -127,7 +539,69 @@ impl<'a> ASTOperations for Parser<'a> {
         }
 
         if self.match_token_type(&[Number, Str]) {
-            return Ok(Expr::Literal(self.previous().literal.clone().unwrap()));
+            let token = self.previous();
+
+            return token
+                .literal
+                .clone()
+                .map(Expr::Literal)
+                .ok_or(ParserError::UndefinedLiteral { span: token.span });
+        }
+
+        if self.match_token_type(&[This]) {
+            return Ok(Expr::This(self.previous().clone()));
+        }
+
+        if self.match_token_type(&[Super]) {
+            let keyword = self.previous().clone();
+
+            self.consume(Dot, "Expected '.' after 'super'".into())?;
+
+            let method = self
+                .consume(Identifier, "Expected superclass method name".into())?
+                .clone();
+
+            return Ok(Expr::Super(keyword, method));
+        }
+
+        if self.match_token_type(&[Identifier]) {
+            return Ok(Expr::Variable(self.previous().clone()));
+        }
+
+        if self.match_token_type(&[InterpolationStart]) {
+            return self.interpolation();
+        }
+
+        if self.match_token_type(&[LeftBracket]) {
+            return self.list();
+        }
+
+        if self.match_token_type(&[LeftBrace]) {
+            return self.map();
+        }
+
+        if self.match_token_type(&[Fun]) {
+            self.consume(LeftParen, "Expected '(' after 'fun'".into())?;
+
+            let params = self.parameters()?;
+
+            self.consume(LeftBrace, "Expected '{' before function body".into())?;
+
+            let body = self.function_body()?;
+
+            return Ok(Expr::Lambda(params, body));
+        }
+
+        if self.current_eq(LeftParen) && self.is_at_arrow_parameters() {
+            self.advance();
+
+            let params = self.parameters()?;
+            let arrow = self
+                .consume(Arrow, "Expected '=>' after parameters".into())?
+                .clone();
+            let body = self.assignment()?;
+
+            return Ok(Expr::Lambda(params, vec![Stmt::Return(arrow, Some(body))]));
         }
 
         if self.match_token_type(&[LeftParen]) {
@@ -135,27 +609,384 @@ impl<'a> ASTOperations for Parser<'a> {
 
             self.consume(RightParen, "Expected ')' after expression".into())?;
 
+            if self.current_eq(Arrow) {
+                return Err(ParserError::ParseError {
+                    message: "Arrow function parameters must be names".into(),
+                    span: self.peek().span,
+                });
+            }
+
             return Ok(Expr::Grouping(Box::from(expr)));
         }
 
         Err(ParserError::PrimaryError {
-            line: self.peek().line,
+            span: self.peek().span,
         })
     }
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokens: &'a Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+impl<'src, I> Parser<'src, I>
+where
+    I: Iterator<Item = Result<Token<'src>, ScannerError>>,
+{
+    pub fn new(tokens: I) -> Self {
+        let mut parser = Self {
+            tokens,
+            lookahead: VecDeque::new(),
+            previous: None,
+            scanner_error: None,
+            errors: vec![],
+            loop_depth: 0,
+        };
+
+        parser.fill_lookahead(1);
+
+        parser
     }
 
-    pub fn parse(&mut self) -> Result<Expr, ParserError> {
-        self.expression()
+    /// Parses the stream into the statements of a program. A statement with a syntax error is skipped and the error
+    /// recorded, so every error in the program is found in one go. Scanner errors are recorded as they are pulled and
+    /// the bad text is skipped, unless the error swallowed the rest of the source. Parsing stops there, and that error
+    /// is recorded last.
+    pub fn parse(&mut self) -> (Vec<Stmt<'src>>, &Vec<ParserError>) {
+        let mut statements = vec![];
+
+        while !self.is_at_end() {
+            statements.extend(self.declaration_or_synchronise());
+        }
+
+        if let Some(scanner_error) = self.scanner_error.take() {
+            self.errors.push(scanner_error.into());
+        }
+
+        (statements, &self.errors)
     }
 
-    fn advance(&mut self) -> &Token {
+    /// Parses a declaration. If it has an error, the error is recorded and the parser skips ahead to the next statement.
+    fn declaration_or_synchronise(&mut self) -> Option<Stmt<'src>> {
+        match self.declaration() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                // Once the stream has errored, an EOF stands in for the rest of it. Running into that EOF is down to
+                // the scanner error, which is reported instead.
+                if !(self.is_at_end() && self.scanner_error.is_some()) {
+                    self.errors.push(error);
+                }
+
+                self.synchronise();
+
+                None
+            }
+        }
+    }
+
+    /// Pulls tokens from the stream until `count` tokens are waiting in the lookahead, recording any scanner errors
+    /// along the way. Once the stream ends, or errors in a way that ends the input, an EOF token is added in its place
+    /// and nothing more is pulled.
+    fn fill_lookahead(&mut self, count: usize) {
+        while self.lookahead.len() < count {
+            if self
+                .lookahead
+                .back()
+                .is_some_and(|token| token.token == EOF)
+            {
+                return;
+            }
+
+            let token = match self.tokens.next() {
+                Some(Ok(token)) => token,
+                Some(Err(scanner_error)) if !scanner_error.ends_input() => {
+                    self.errors.push(scanner_error.into());
+
+                    continue;
+                }
+                Some(Err(scanner_error)) => {
+                    self.scanner_error = Some(scanner_error);
+
+                    self.eof_token()
+                }
+                None => self.eof_token(),
+            };
+
+            self.lookahead.push_back(token);
+        }
+    }
+
+    /// An EOF token positioned right after the last token seen, for streams that end without one.
+    fn eof_token(&self) -> Token<'src> {
+        let last_span = self
+            .lookahead
+            .back()
+            .or(self.previous.as_ref())
+            .map(|token| token.span)
+            .unwrap_or_default();
+
+        Token {
+            token: EOF,
+            lexeme: "\0",
+            literal: None,
+            span: Span {
+                start: last_span.end,
+                ..last_span
+            },
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
+        }
+    }
+
+    /// Parses the elements of a list literal, the `[` having just been matched. The last element can have a trailing comma.
+    fn list(&mut self) -> Result<Expr<'src>, ParserError> {
+        let mut elements = vec![];
+
+        while !self.current_eq(RightBracket) {
+            elements.push(self.assignment()?);
+
+            if !self.match_token_type(&[Comma]) {
+                break;
+            }
+        }
+
+        self.consume(RightBracket, "Expected ']' after list elements".into())?;
+
+        Ok(Expr::List(elements))
+    }
+
+    /// Parses the body of a loop, inside which `break` and `continue` can be used.
+    fn loop_body(&mut self) -> Result<Stmt<'src>, ParserError> {
+        self.loop_depth += 1;
+
+        let body = self.statement();
+
+        self.loop_depth -= 1;
+
+        body
+    }
+
+    /// Parses the statements of a function's body, the `{` having just been matched. Loops the function is declared in
+    /// don't count inside of it.
+    fn function_body(&mut self) -> Result<Vec<Stmt<'src>>, ParserError> {
+        let enclosing_loop_depth = mem::take(&mut self.loop_depth);
+
+        let body = self.block();
+
+        self.loop_depth = enclosing_loop_depth;
+
+        body
+    }
+
+    /// Parses the entries of a map literal, the `{` having just been matched. A key is either a string, interpolated or
+    /// not, or any expression in brackets, as in `{"name": "wrig", "${id}": id, [key]: value}`. The last entry can have
+    /// a trailing comma.
+    fn map(&mut self) -> Result<Expr<'src>, ParserError> {
+        let mut entries = vec![];
+
+        while !self.current_eq(RightBrace) {
+            let key = if self.match_token_type(&[LeftBracket]) {
+                let key = self.expression()?;
+
+                self.consume(RightBracket, "Expected ']' after map key".into())?;
+
+                key
+            } else if self.match_token_type(&[Str]) {
+                let token = self.previous();
+
+                token
+                    .literal
+                    .clone()
+                    .map(Expr::Literal)
+                    .ok_or(ParserError::UndefinedLiteral { span: token.span })?
+            } else if self.match_token_type(&[InterpolationStart]) {
+                self.interpolation()?
+            } else {
+                return Err(ParserError::ParseError {
+                    message: "Expected a string or a '[' expression ']' as map key".into(),
+                    span: self.peek().span,
+                });
+            };
+
+            self.consume(Colon, "Expected ':' after map key".into())?;
+
+            entries.push((key, self.assignment()?));
+
+            if !self.match_token_type(&[Comma]) {
+                break;
+            }
+        }
+
+        self.consume(RightBrace, "Expected '}' after map entries".into())?;
+
+        Ok(Expr::Map(entries))
+    }
+
+    /// Whether the current `{` opens a map literal rather than a block. Only matters at the start of a statement, where
+    /// it's a map if the first key and its `:` follow, as in `{"a": 1}`, `{"${k}": v}` or `{[k]: v}`. So `{}` is an
+    /// empty block.
+    fn is_at_map_literal(&mut self) -> bool {
+        match self.peek_ahead(1).token {
+            Str => self.peek_ahead(2).token == Colon,
+            InterpolationStart => {
+                // Holes can hold strings that are interpolated themselves, so look past the matching end for the `:`.
+                let mut depth = 0;
+                let mut distance = 1;
+
+                loop {
+                    match self.peek_ahead(distance).token {
+                        InterpolationStart => depth += 1,
+                        InterpolationEnd if depth == 1 => {
+                            return self.peek_ahead(distance + 1).token == Colon
+                        }
+                        InterpolationEnd => depth -= 1,
+                        EOF => return false,
+                        _ => (),
+                    }
+
+                    distance += 1;
+                }
+            }
+            LeftBracket => {
+                // A block can start with a list, `{ [1, 2]; }`, so look past the matching `]` for the `:`.
+                let mut depth = 0;
+                let mut distance = 1;
+
+                loop {
+                    match self.peek_ahead(distance).token {
+                        LeftBracket => depth += 1,
+                        RightBracket if depth == 1 => {
+                            return self.peek_ahead(distance + 1).token == Colon
+                        }
+                        RightBracket => depth -= 1,
+                        EOF => return false,
+                        _ => (),
+                    }
+
+                    distance += 1;
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses the parameters of a function up to and including the `)`, the `(` having just been matched.
+    fn parameters(&mut self) -> Result<Vec<Token<'src>>, ParserError> {
+        let mut params = vec![];
+
+        if !self.current_eq(RightParen) {
+            loop {
+                if params.len() == MAX_ARGUMENTS {
+                    self.errors.push(ParserError::ParseError {
+                        message: format!("Can't have more than {MAX_ARGUMENTS} parameters"),
+                        span: self.peek().span,
+                    });
+                }
+
+                params.push(
+                    self.consume(Identifier, "Expected parameter name".into())?
+                        .clone(),
+                );
+
+                if !self.match_token_type(&[Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(RightParen, "Expected ')' after parameters".into())?;
+
+        Ok(params)
+    }
+
+    /// Whether the current `(` opens the parameters of an arrow function, `(a, b) => a + b`, rather than a grouping.
+    /// Both start the same way, so this looks ahead for the `=>` after a list of names.
+    fn is_at_arrow_parameters(&mut self) -> bool {
+        let mut distance = 1;
+
+        if self.peek_ahead(distance).token == Identifier {
+            distance += 1;
+
+            while self.peek_ahead(distance).token == Comma {
+                if self.peek_ahead(distance + 1).token != Identifier {
+                    return false;
+                }
+
+                distance += 2;
+            }
+        }
+
+        self.peek_ahead(distance).token == RightParen
+            && self.peek_ahead(distance + 1).token == Arrow
+    }
+
+    /// Parses the arguments of a call, the `(` having just been matched.
+    fn finish_call(&mut self, callee: Expr<'src>) -> Result<Expr<'src>, ParserError> {
+        let mut arguments = vec![];
+
+        if !self.current_eq(RightParen) {
+            loop {
+                if arguments.len() == MAX_ARGUMENTS {
+                    self.errors.push(ParserError::ParseError {
+                        message: format!("Can't have more than {MAX_ARGUMENTS} arguments"),
+                        span: self.peek().span,
+                    });
+                }
+
+                arguments.push(self.assignment()?);
+
+                if !self.match_token_type(&[Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self
+            .consume(RightParen, "Expected ')' after arguments".into())?
+            .clone();
+
+        Ok(Expr::Call(Box::from(callee), paren, arguments))
+    }
+
+    /// Parses the rest of an interpolated string, the `InterpolationStart` token having just been matched.
+    fn interpolation(&mut self) -> Result<Expr<'src>, ParserError> {
+        let mut parts = vec![];
+
+        self.push_interpolation_string(&mut parts)?;
+
+        loop {
+            parts.push(self.expression()?);
+
+            if self.match_token_type(&[InterpolationSegment]) {
+                self.push_interpolation_string(&mut parts)?;
+
+                continue;
+            }
+
+            self.consume(
+                InterpolationEnd,
+                "Expected '}' after interpolated expression".into(),
+            )?;
+            self.push_interpolation_string(&mut parts)?;
+
+            return Ok(Expr::Interpolation(parts));
+        }
+    }
+
+    /// Adds the string carried by the previous interpolation token to the parts, skipping empty strings.
+    fn push_interpolation_string(&self, parts: &mut Vec<Expr<'src>>) -> Result<(), ParserError> {
+        let token = self.previous();
+
+        match &token.literal {
+            Some(LiteralType::Str(value)) if value.is_empty() => (),
+            Some(literal) => parts.push(Expr::Literal(literal.clone())),
+            None => return Err(ParserError::UndefinedLiteral { span: token.span }),
+        };
+
+        Ok(())
+    }
+
+    fn advance(&mut self) -> &Token<'src> {
         if !self.is_at_end() {
-            self.current += 1;
+            self.previous = self.lookahead.pop_front();
+
+            self.fill_lookahead(1);
         }
 
         self.previous()
@@ -165,17 +996,18 @@ impl<'a> Parser<'a> {
         &mut self,
         token_type: TokenType,
         on_fail_msg: String,
-    ) -> Result<&Token, ParserError> {
+    ) -> Result<&Token<'src>, ParserError> {
         if self.match_token_type(&[token_type]) {
-            return Ok(self.advance());
+            return Ok(self.previous());
         }
 
         Err(ParserError::ParseError {
             message: on_fail_msg,
+            span: self.peek().span,
         })
     }
 
-    #[allow(dead_code)]
+    /// Skips tokens until the end of the current statement, or the start of the next one, after a syntax error.
     fn synchronise(&mut self) {
         self.advance();
 
@@ -185,12 +1017,8 @@ impl<'a> Parser<'a> {
             }
 
             match self.peek().token {
-                Class | Fun | Var | For | If | While | Print | Return => return,
-                _ => {
-                    self.advance();
-
-                    return;
-                }
+                Class | Fun | Var | For | If | While | Print | Return | Break | Continue => return,
+                _ => self.advance(),
             };
         }
     }
@@ -219,101 +1047,927 @@ impl<'a> Parser<'a> {
         self.peek().token == TokenType::EOF
     }
 
-    fn peek(&self) -> &Token {
+    fn peek(&self) -> &Token<'src> {
         debug_assert!(
-            self.current < self.tokens.len(),
-            "Attempt to get token using an out of bounds index"
+            !self.lookahead.is_empty(),
+            "Attempt to get token with nothing in the lookahead"
         );
 
-        self.tokens.get(self.current).unwrap()
+        self.lookahead.front().unwrap()
     }
 
-    fn previous(&self) -> &Token {
-        let index = self.current - 1;
+    /// The token `distance` places after the current one, pulled from the stream if it hasn't been yet. Past the end of
+    /// the stream this is the EOF.
+    fn peek_ahead(&mut self, distance: usize) -> &Token<'src> {
+        self.fill_lookahead(distance + 1);
+
+        let index = distance.min(self.lookahead.len() - 1);
+
+        &self.lookahead[index]
+    }
 
+    fn previous(&self) -> &Token<'src> {
         debug_assert!(
-            index < self.tokens.len(),
-            "Attempt to get previous token using an out of bounds index"
+            self.previous.is_some(),
+            "Attempt to get previous token before any token was consumed"
         );
 
-        self.tokens.get(index).unwrap()
+        self.previous.as_ref().unwrap()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+
     use super::Parser;
     use crate::components::{
         parser::ParserError,
-        token_components::{LiteralType, Token, TokenType::*},
+        parser_components::{Expr, Stmt},
+        scanner::ScannerError,
+        token_components::{LiteralType, Span, Token, TokenType::*},
         Scanner,
     };
 
     #[test]
     fn should_add() {
         let one = Token {
-            lexeme: "1".into(),
-            line: 1,
+            lexeme: "1",
             literal: Some(LiteralType::Number(1.0)),
             token: Number,
+            span: Span::default(),
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
         };
 
         let plus = Token {
-            lexeme: "+".into(),
-            line: 1,
+            lexeme: "+",
             literal: None,
             token: Plus,
+            span: Span::default(),
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
         };
 
         let two = Token {
-            lexeme: "2".into(),
-            line: 1,
+            lexeme: "2",
             literal: Some(LiteralType::Number(2.0)),
             token: Number,
+            span: Span::default(),
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
         };
 
         let semi = Token {
-            lexeme: ';'.into(),
-            line: 1,
+            lexeme: ";",
             literal: None,
             token: Semicolon,
+            span: Span::default(),
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
         };
 
         let scanned_tokens = vec![one, plus, two, semi];
 
-        let mut parser = Parser::new(&scanned_tokens);
-        let expr = parser.parse();
+        let mut parser = Parser::new(scanned_tokens.into_iter().map(Ok));
+        let (statements, errors) = parser.parse();
 
-        assert_eq!(expr.unwrap().to_string(), "(+ 1 2)");
+        assert!(errors.is_empty());
+
+        assert_eq!(statements[0].to_string(), "(expr (+ 1 2))");
     }
 
     #[test]
     fn input_from_scanner() {
-        let mut scanner = Scanner::new("1 + 2 <= 5 + 7");
-        let (tokens, _) = scanner.scan_tokens();
-        let mut parser = Parser::new(&tokens);
-        let expr = parser.parse();
+        let scanner = Scanner::new("1 + 2 <= 5 + 7;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
 
-        assert_eq!(expr.unwrap().to_string(), "(<= (+ 1 2) (+ 5 7))");
+        assert_eq!(statements[0].to_string(), "(expr (<= (+ 1 2) (+ 5 7)))");
     }
 
     #[test]
     fn should_report_paren_error() {
-        let mut scanner = Scanner::new("1 + 2 + (5 + 7");
-        let (tokens, _) = scanner.scan_tokens();
-        let mut parser = Parser::new(&tokens);
-        let expr_err = parser.parse().expect_err("Successfully parsed");
-
-        assert!(matches!(expr_err, ParserError::ParseError { message: _ }))
+        let scanner = Scanner::new("1 + 2 + (5 + 7");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert!(matches!(
+            errors[0],
+            ParserError::ParseError {
+                message: _,
+                span: _
+            }
+        ))
     }
 
     #[test]
     fn should_report_primary_error() {
-        let mut scanner = Scanner::new("class + 2 + 1");
-        let (tokens, _) = scanner.scan_tokens();
-        let mut parser = Parser::new(&tokens);
-        let expr_err = parser.parse().expect_err("Successfully parsed");
+        let scanner = Scanner::new("else + 2 + 1");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert!(matches!(errors[0], ParserError::PrimaryError { span: _ }))
+    }
+
+    #[test]
+    fn should_report_error_span() {
+        let scanner = Scanner::new("1 +\n  class");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert!(matches!(
+            errors[0],
+            ParserError::PrimaryError {
+                span: Span {
+                    start: 6,
+                    end: 11,
+                    line: 2,
+                    column: 3
+                }
+            }
+        ))
+    }
+
+    #[test]
+    fn should_parse_interpolation() {
+        let scanner = Scanner::new("\"a ${1 + 2} b ${\"c ${3}\"}\";");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            statements[0].to_string(),
+            "(expr (interpolate a  (+ 1 2)  b  (interpolate c  3)))"
+        );
+    }
+
+    #[test]
+    fn should_report_unclosed_interpolation() {
+        let scanner = Scanner::new("\"a ${1 + 2 \"b\"}\"");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert!(matches!(errors[0], ParserError::ParseError { .. }))
+    }
+
+    #[test]
+    fn should_stop_at_scanner_error_that_ends_input() {
+        let mut pulled = 0;
+        let scanner = Scanner::new("1 + \"2 + 3 + 4").inspect(|_| pulled += 1);
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            ParserError::ScannerError(ScannerError::UnterminatedString { .. })
+        ));
+        drop(parser);
+        assert_eq!(pulled, 3);
+    }
+
+    #[test]
+    fn should_keep_parsing_after_recoverable_scanner_errors() {
+        let scanner = Scanner::new("print 1 #;\nprint @3;\nprint (4;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec!["(print 1)", "(print 3)"]
+        );
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(
+            errors[0],
+            ParserError::ScannerError(ScannerError::UnexpectedToken {
+                span: Span { line: 1, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            errors[1],
+            ParserError::ScannerError(ScannerError::UnexpectedToken {
+                span: Span { line: 2, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            errors[2],
+            ParserError::ParseError {
+                span: Span { line: 3, .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn should_only_report_the_escape_in_a_bad_string() {
+        for source in [r#"print "a\q";"#, r#"print "a\q ${b} c";"#] {
+            let scanner = Scanner::new(source);
+            let mut parser = Parser::new(scanner);
+            let (statements, errors) = parser.parse();
+
+            assert_eq!(statements.len(), 1, "{source}");
+            assert_eq!(errors.len(), 1, "{source}: {errors:?}");
+            assert!(matches!(
+                errors[0],
+                ParserError::ScannerError(ScannerError::InvalidEscape { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn should_only_report_the_number_in_a_bad_number() {
+        let scanner = Scanner::new("var x = 0b102;\nprint x;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(matches!(
+            errors[0],
+            ParserError::ScannerError(ScannerError::InvalidNumber { .. })
+        ));
+    }
+
+    #[test]
+    fn should_only_pull_tokens_it_needs() {
+        let pulled = Cell::new(0);
+        let scanner = Scanner::new("(1); 2;").inspect(|_| pulled.set(pulled.get() + 1));
+        let mut parser = Parser::new(scanner);
+
+        assert_eq!(pulled.get(), 1);
+
+        parser.parse();
+
+        assert_eq!(pulled.get(), 7);
+    }
+
+    #[test]
+    fn should_parse_statements() {
+        let scanner = Scanner::new("var a; var b = 1 + 2;\nprint b;\n{ print \"hi\"; { 3; } }");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "(var a)",
+                "(var b (+ 1 2))",
+                "(print b)",
+                "(block (print hi) (block (expr 3)))"
+            ]
+        );
+    }
+
+    #[test]
+    fn should_attach_doc_comment_to_var() {
+        let scanner = Scanner::new("/// The answer.\nvar answer = 42;\nvar other;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+
+        assert!(matches!(
+            &statements[0],
+            Stmt::Var(_, _, Some(doc)) if doc == "The answer."
+        ));
+        assert!(matches!(&statements[1], Stmt::Var(_, None, None)));
+    }
+
+    #[test]
+    fn should_report_missing_semicolon() {
+        let scanner = Scanner::new("print 1\nprint 2;");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert!(matches!(
+            errors[0],
+            ParserError::ParseError {
+                span: Span {
+                    line: 2,
+                    column: 1,
+                    ..
+                },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn should_report_unclosed_block() {
+        let scanner = Scanner::new("{ print 1;");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert!(matches!(errors[0], ParserError::ParseError { .. }));
+    }
+
+    #[test]
+    fn should_report_every_error() {
+        let scanner =
+            Scanner::new("print 1;\nprint (2;\nvar = 3;\nprint 4;\n{ print ; print 5; }\n6 +;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec!["(print 1)", "(print 4)", "(block (print 5))"]
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| match error {
+                    ParserError::ParseError { span, .. } | ParserError::PrimaryError { span } =>
+                        span.line,
+                    error => panic!("Unexpected error {error}"),
+                })
+                .collect::<Vec<_>>(),
+            vec![2, 3, 5, 6]
+        );
+    }
+
+    #[test]
+    fn should_report_scanner_error_after_parse_errors() {
+        let scanner = Scanner::new("print );\nprint 1;\nprint 2 + /* 3");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(statements.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], ParserError::PrimaryError { .. }));
+        assert!(matches!(
+            errors[1],
+            ParserError::ScannerError(ScannerError::UnterminatedComment { .. })
+        ));
+    }
+
+    #[test]
+    fn should_parse_logical_operators() {
+        let scanner = Scanner::new("1 or 2 and 3 == 4 or !5;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements[0].to_string(),
+            "(expr (or (or 1 (and 2 (== 3 4))) (! 5)))"
+        );
+        assert!(matches!(
+            &statements[0],
+            Stmt::Expression(Expr::Logical(_, Token { token: Or, .. }, _))
+        ));
+    }
+
+    #[test]
+    fn should_parse_assignment() {
+        let scanner = Scanner::new("var x = 1;\na = b = x + 1;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(statements[0].to_string(), "(var x 1)");
+        assert_eq!(statements[1].to_string(), "(expr (= a (= b (+ x 1))))");
+    }
+
+    #[test]
+    fn should_report_invalid_assignment_target() {
+        let scanner = Scanner::new("a + b = c;\n(a) = 1 = d;\nprint a;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec!["(expr (+ a b))", "(expr (group a))", "(print a)"]
+        );
+        assert!(matches!(
+            errors[..],
+            [
+                ParserError::InvalidAssignmentTarget {
+                    span: Span {
+                        line: 1,
+                        column: 7,
+                        ..
+                    }
+                },
+                ParserError::InvalidAssignmentTarget {
+                    span: Span {
+                        line: 2,
+                        column: 9,
+                        ..
+                    }
+                },
+                ParserError::InvalidAssignmentTarget {
+                    span: Span {
+                        line: 2,
+                        column: 5,
+                        ..
+                    }
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn should_parse_if_and_while() {
+        let scanner = Scanner::new("if (a) print 1; else { print 2; }\nwhile (a < 3) a = a + 1;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "(if a (print 1) (block (print 2)))",
+                "(while (< a 3) (expr (= a (+ a 1))))"
+            ]
+        );
+    }
+
+    #[test]
+    fn should_bind_else_to_nearest_if() {
+        let scanner = Scanner::new("if (a) if (b) print 1; else print 2;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements[0].to_string(),
+            "(if a (if b (print 1) (print 2)))"
+        );
+    }
+
+    #[test]
+    fn should_desugar_for_into_while() {
+        let scanner = Scanner::new(
+            "for (var i = 0; i < 3; i = i + 1) print i;\nfor (i = 0; ; ) print i;\nfor (;;) {}",
+        );
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "(block (var i 0) (while (< i 3) (print i) (= i (+ i 1))))",
+                "(block (expr (= i 0)) (while true (print i)))",
+                "(while true (block))"
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_missing_paren_in_control_flow() {
+        let scanner = Scanner::new("if a) print 1;\nwhile (a print 2;\nfor (;;\nprint 3;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(statements.len(), 1);
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn should_parse_functions() {
+        let scanner = Scanner::new(
+            "/// Adds two numbers.\nfun add(a, b) { return a + b; }\nfun nothing() { return; }\nprint add(1, 2)(3)();",
+        );
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "(fun add (a b) (return (+ a b)))",
+                "(fun nothing () (return))",
+                "(print (call (call (call add 1 2) 3)))"
+            ]
+        );
+        assert!(matches!(
+            &statements[0],
+            Stmt::Function(_, _, _, Some(doc)) if doc == "Adds two numbers."
+        ));
+    }
+
+    #[test]
+    fn should_keep_closing_paren_of_call() {
+        let scanner = Scanner::new("f(1,\n  2);");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert!(matches!(
+            &statements[0],
+            Stmt::Expression(Expr::Call(
+                _,
+                Token {
+                    token: RightParen,
+                    span: Span {
+                        line: 2,
+                        column: 4,
+                        ..
+                    },
+                    ..
+                },
+                _
+            ))
+        ));
+    }
+
+    #[test]
+    fn should_report_too_many_arguments_and_parameters() {
+        let names = (0..256)
+            .map(|n| format!("a{n}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let source = format!(
+            "fun f({names}) {{}}\nf({names});\nf({});",
+            names.replace("a", "1")
+        );
+        let scanner = Scanner::new(&source);
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(statements.len(), 3);
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Parse error on line 1, column 1427. Can't have more than 255 parameters",
+                "Parse error on line 2, column 1423. Can't have more than 255 arguments",
+                "Parse error on line 3, column 1423. Can't have more than 255 arguments",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_parse_classes() {
+        let scanner = Scanner::new(
+            "/// A point.\nclass Point < Shape {\n  /// Makes a point.\n  init(x) { this.x = x; }\n  area() { return super.area() * this.x; }\n}\nclass Empty {}",
+        );
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "(class Point < Shape (fun init (x) (expr (set this x x))) (fun area () (return (* (call (super area)) (get this x)))))",
+                "(class Empty)"
+            ]
+        );
+
+        let Stmt::Class(_, _, methods, Some(doc)) = &statements[0] else {
+            panic!("Expected a documented class");
+        };
+
+        assert_eq!(doc, "A point.");
+        assert!(matches!(
+            &methods[0],
+            Stmt::Function(_, _, _, Some(doc)) if doc == "Makes a point."
+        ));
+        assert!(matches!(&methods[1], Stmt::Function(_, _, _, None)));
+    }
+
+    #[test]
+    fn should_parse_property_chains() {
+        let scanner = Scanner::new("a.b(1).c = d.e = f;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            statements[0].to_string(),
+            "(expr (set (call (get a b) 1) c (set d e f)))"
+        );
+    }
+
+    #[test]
+    fn should_report_class_inheriting_from_itself() {
+        let scanner = Scanner::new("class Loop < Loop {}\nsuper;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(statements.len(), 1);
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Parse error on line 1, column 14. A class can't inherit from itself",
+                "Parse error on line 2, column 6. Expected '.' after 'super'",
+            ]
+        );
+    }
+
+    /// Parses a single expression statement and gives back the expression as a string.
+    fn parse_expression(source: &str) -> String {
+        let scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty(), "{errors:?}");
+
+        match &statements[..] {
+            [Stmt::Expression(expr)] => expr.to_string(),
+            statements => panic!("Expected one expression statement, got {statements:?}"),
+        }
+    }
+
+    #[test]
+    fn should_parse_conditional_and_comma_precedence() {
+        assert_eq!(
+            parse_expression("a ? b : c ? d : e;"),
+            "(?: a b (?: c d e))"
+        );
+        assert_eq!(
+            parse_expression("a or b ? c and d : e;"),
+            "(?: (or a b) (and c d) e)"
+        );
+        assert_eq!(parse_expression("x = a ? b : c;"), "(= x (?: a b c))");
+        assert_eq!(
+            parse_expression("a ? b = 1, c : d;"),
+            "(?: a (, (= b 1) c) d)"
+        );
+        assert_eq!(parse_expression("a, b = c, d;"), "(, (, a (= b c)) d)");
+        assert_eq!(
+            parse_expression("f((a, b), c);"),
+            "(call f (group (, a b)) c)"
+        );
+    }
 
-        assert!(matches!(expr_err, ParserError::PrimaryError { line: _ }))
+    #[test]
+    fn should_report_missing_colon() {
+        let scanner = Scanner::new("a ? b;\nprint a ?\n b c;");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Parse error on line 1, column 6. Expected ':' to match the '?' on line 1, column 3",
+                "Parse error on line 3, column 4. Expected ':' to match the '?' on line 2, column 9",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_not_parse_comma_in_var_initialiser() {
+        let scanner = Scanner::new("var a = 1, b = 2;");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec!["Parse error on line 1, column 10. Expected ';' after variable declaration"]
+        );
+    }
+
+    #[test]
+    fn should_report_assignment_to_conditional() {
+        let scanner = Scanner::new("a ? b : c = d;");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert!(matches!(
+            errors[..],
+            [ParserError::InvalidAssignmentTarget { .. }]
+        ));
+    }
+
+    #[test]
+    fn should_parse_lambdas() {
+        assert_eq!(
+            parse_expression("on(\"click\", fun (event) { print event; });"),
+            "(call on click (lambda (event) (print event)))"
+        );
+        assert_eq!(
+            parse_expression("map(xs, (a, b) => a + b, () => 1, (x) => (y) => x);"),
+            "(call map xs (lambda (a b) (return (+ a b))) (lambda () (return 1)) (lambda (x) (return (lambda (y) (return x)))))"
+        );
+        assert_eq!(parse_expression("fun () {}();"), "(call (lambda ()))");
+    }
+
+    #[test]
+    fn should_tell_grouping_from_arrow_parameters() {
+        assert_eq!(parse_expression("(a);"), "(group a)");
+        assert_eq!(parse_expression("(a, b);"), "(group (, a b))");
+        assert_eq!(
+            parse_expression("(a) + (b) => b;"),
+            "(+ (group a) (lambda (b) (return b)))"
+        );
+        assert_eq!(parse_expression("(a, 1);"), "(group (, a 1))");
+    }
+
+    #[test]
+    fn should_report_arrow_with_invalid_parameters() {
+        let scanner = Scanner::new("(a, 1) => a;");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec!["Parse error on line 1, column 8. Arrow function parameters must be names"]
+        );
+    }
+
+    #[test]
+    fn should_parse_lists() {
+        assert_eq!(parse_expression("[];"), "(list)");
+        assert_eq!(
+            parse_expression("[1, 2 + 3, [4],];"),
+            "(list 1 (+ 2 3) (list 4))"
+        );
+        assert_eq!(
+            parse_expression("xs[i][0] + f()[1];"),
+            "(+ (index (index xs i) 0) (index (call f) 1))"
+        );
+        assert_eq!(
+            parse_expression("xs[i] = ys[0] = 1;"),
+            "(set-index xs i (set-index ys 0 1))"
+        );
+        assert_eq!(
+            parse_expression("a.b[1].c = 2;"),
+            "(set (index (get a b) 1) c 2)"
+        );
+    }
+
+    #[test]
+    fn should_report_unclosed_list_and_index() {
+        let scanner = Scanner::new("[1, 2;\nxs[1;\n[,];");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(statements.is_empty());
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Parse error on line 1, column 6. Expected ']' after list elements",
+                "Parse error on line 2, column 5. Expected ']' after index",
+                "No primary found on line 3, column 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_parse_maps() {
+        assert_eq!(
+            parse_expression("x = {\"name\": \"wrig\", [key]: {}, [\"a\" + 1]: [1],};"),
+            "(= x (map (name wrig) (key (map)) ((+ a 1) (list 1))))"
+        );
+        assert_eq!(
+            parse_expression("x = {\"a${b}\": 1};"),
+            "(= x (map ((interpolate a b) 1)))"
+        );
+        assert_eq!(
+            parse_expression("config[\"name\"] = map[key];"),
+            "(set-index config name (index map key))"
+        );
+    }
+
+    #[test]
+    fn should_tell_map_from_block_at_statement_start() {
+        let scanner =
+            Scanner::new("{\"a\": 1};\n{[k]: v}[k];\n{}\n{\"a\";}\n{[1, [2]]; }\n{[[1][0]]: 2};\n{\"a${\"${b}\"}\": 1};\n{\"a${b}\";}");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "(expr (map (a 1)))",
+                "(expr (index (map (k v)) k))",
+                "(block)",
+                "(block (expr a))",
+                "(block (expr (list 1 (list 2))))",
+                "(expr (map ((index (list 1) 0) 2)))",
+                "(expr (map ((interpolate a (interpolate b)) 1)))",
+                "(block (expr (interpolate a b)))",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_invalid_map_key() {
+        let scanner = Scanner::new("x = {a: 1};\nx = {\"a\" 1};");
+        let mut parser = Parser::new(scanner);
+        let (_, errors) = parser.parse();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Parse error on line 1, column 6. Expected a string or a '[' expression ']' as map key",
+                "Parse error on line 2, column 10. Expected ':' after map key",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_parse_break_and_continue() {
+        let scanner = Scanner::new(
+            "while (true) { if (a) break; continue; }\nfor (i = 0; i < 3; i = i + 1) { if (i) continue; fun f() { while (b) break; } }",
+        );
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "(while true (block (if a (break)) (continue)))",
+                "(block (expr (= i 0)) (while (< i 3) (block (if i (continue)) (fun f () (while b (break)))) (= i (+ i 1))))",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_keep_for_increment_on_while_for_continue() {
+        let scanner = Scanner::new("for (;; i = i + 1) continue;");
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert!(errors.is_empty());
+        assert!(matches!(
+            &statements[0],
+            Stmt::While(_, body, Some(Expr::Assign(..))) if matches!(**body, Stmt::Continue(_))
+        ));
+    }
+
+    #[test]
+    fn should_report_break_and_continue_outside_loop() {
+        let scanner = Scanner::new(
+            "break;\nif (a) continue;\nwhile (a) { fun f() { break; } }\nfor (;;) g(fun () { continue; });\nwhile (a) h(() => 1);",
+        );
+        let mut parser = Parser::new(scanner);
+        let (statements, errors) = parser.parse();
+
+        assert_eq!(statements.len(), 5);
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Parse error on line 1, column 1. Can't use 'break' outside of a loop",
+                "Parse error on line 2, column 8. Can't use 'continue' outside of a loop",
+                "Parse error on line 3, column 23. Can't use 'break' outside of a loop",
+                "Parse error on line 4, column 21. Can't use 'continue' outside of a loop",
+            ]
+        );
     }
 }
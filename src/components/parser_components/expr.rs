@@ -1,20 +1,122 @@
+use super::Stmt;
 use crate::components::token_components::{LiteralType, Token};
 
 #[derive(Debug)]
-pub enum Expr {
-    Binary(Box<Expr>, Token, Box<Expr>),
-    Grouping(Box<Expr>),
+pub enum Expr<'src> {
+    /// The variable's name and the value assigned to it.
+    Assign(Token<'src>, Box<Expr<'src>>),
+    Binary(Box<Expr<'src>>, Token<'src>, Box<Expr<'src>>),
+    /// The callee, the `)` closing the arguments (so errors can point at the call) and the arguments.
+    Call(Box<Expr<'src>>, Token<'src>, Vec<Expr<'src>>),
+    /// The object and the name of the property read from it.
+    Get(Box<Expr<'src>>, Token<'src>),
+    /// The condition and the expressions for when it's true and false, `condition ? a : b`.
+    Conditional(Box<Expr<'src>>, Box<Expr<'src>>, Box<Expr<'src>>),
+    Grouping(Box<Expr<'src>>),
+    /// The list or value being indexed, the `]` closing the index (so errors can point at it) and the index.
+    Index(Box<Expr<'src>>, Token<'src>, Box<Expr<'src>>),
+    /// The string and expression parts of an interpolated string, in source order.
+    Interpolation(Vec<Expr<'src>>),
+    /// A function without a name, its parameters and body. An arrow function's body is a single `return`.
+    Lambda(Vec<Token<'src>>, Vec<Stmt<'src>>),
+    List(Vec<Expr<'src>>),
     Literal(LiteralType),
-    Unary(Token, Box<Expr>),
+    /// A map literal's keys and values, in source order.
+    Map(Vec<(Expr<'src>, Expr<'src>)>),
+    /// An `and` or `or`, kept apart from `Binary` because the right side is only evaluated when it's needed.
+    Logical(Box<Expr<'src>>, Token<'src>, Box<Expr<'src>>),
+    /// The object, the name of the property and the value written to it.
+    Set(Box<Expr<'src>>, Token<'src>, Box<Expr<'src>>),
+    /// An assignment to an index, `xs[i] = v`. The same as `Index`, plus the value written.
+    SetIndex(
+        Box<Expr<'src>>,
+        Token<'src>,
+        Box<Expr<'src>>,
+        Box<Expr<'src>>,
+    ),
+    /// The `super` keyword and the name of the superclass method.
+    Super(Token<'src>, Token<'src>),
+    This(Token<'src>),
+    Unary(Token<'src>, Box<Expr<'src>>),
+    Variable(Token<'src>),
 }
 
-impl std::fmt::Display for Expr {
+impl std::fmt::Display for Expr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Assign(name, value) => write!(f, "(= {name} {value})"),
             Self::Binary(left, op, right) => write!(f, "({op} {left} {right})"),
+            Self::Call(callee, _, arguments) => {
+                write!(f, "(call {callee}")?;
+
+                for argument in arguments {
+                    write!(f, " {argument}")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::Conditional(condition, then_branch, else_branch) => {
+                write!(f, "(?: {condition} {then_branch} {else_branch})")
+            }
+            Self::Get(object, name) => write!(f, "(get {object} {name})"),
             Self::Grouping(expr) => write!(f, "(group {expr})"),
+            Self::Index(object, _, index) => write!(f, "(index {object} {index})"),
+            Self::Interpolation(parts) => {
+                write!(f, "(interpolate")?;
+
+                for part in parts {
+                    write!(f, " {part}")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::Lambda(params, body) => {
+                write!(f, "(lambda (")?;
+
+                for (index, param) in params.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " ")?;
+                    }
+
+                    write!(f, "{param}")?;
+                }
+
+                write!(f, ")")?;
+
+                for statement in body {
+                    write!(f, " {statement}")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::List(elements) => {
+                write!(f, "(list")?;
+
+                for element in elements {
+                    write!(f, " {element}")?;
+                }
+
+                write!(f, ")")
+            }
             Self::Literal(literal) => write!(f, "{literal}"),
+            Self::Map(entries) => {
+                write!(f, "(map")?;
+
+                for (key, value) in entries {
+                    write!(f, " ({key} {value})")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::Logical(left, op, right) => write!(f, "({op} {left} {right})"),
+            Self::Set(object, name, value) => write!(f, "(set {object} {name} {value})"),
+            Self::SetIndex(object, _, index, value) => {
+                write!(f, "(set-index {object} {index} {value})")
+            }
+            Self::Super(_, method) => write!(f, "(super {method})"),
+            Self::This(keyword) => write!(f, "{keyword}"),
             Self::Unary(op, right) => write!(f, "({op} {right})"),
+            Self::Variable(name) => write!(f, "{name}"),
         }
     }
 }
@@ -22,29 +124,46 @@ impl std::fmt::Display for Expr {
 #[cfg(test)]
 mod tests {
     use super::Expr;
-    use crate::components::token_components::{LiteralType, Token, TokenType::*};
+    use crate::components::token_components::{LiteralType, Span, Token, TokenType::*};
 
     #[test]
     fn should_stringify_deep() {
         let ast = Expr::Binary(
             Box::from(Expr::Grouping(Box::from(Expr::Unary(
                 Token {
-                    lexeme: "/".into(),
-                    line: 1,
+                    lexeme: "/",
                     literal: None,
                     token: Slash,
+                    span: Span::default(),
+                    leading_trivia: vec![],
+                    trailing_trivia: vec![],
                 },
                 Box::from(Expr::Literal(LiteralType::Number(1.0))),
             )))),
             Token {
-                lexeme: "+".into(),
-                line: 1,
+                lexeme: "+",
                 literal: None,
                 token: Plus,
+                span: Span::default(),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
             },
             Box::from(Expr::Literal(LiteralType::Number(3.0))),
         );
 
         assert_eq!("(+ (group (/ 1)) 3)", ast.to_string());
     }
+
+    #[test]
+    fn should_stringify_nested_conditional() {
+        let literal = |value: f64| Box::from(Expr::Literal(LiteralType::Number(value)));
+
+        let ast = Expr::Conditional(
+            literal(1.0),
+            literal(2.0),
+            Box::from(Expr::Conditional(literal(3.0), literal(4.0), literal(5.0))),
+        );
+
+        assert_eq!("(?: 1 2 (?: 3 4 5))", ast.to_string());
+    }
 }
@@ -1,3 +1,5 @@
 mod expr;
+mod stmt;
 
 pub use expr::*;
+pub use stmt::*;
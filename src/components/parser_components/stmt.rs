@@ -0,0 +1,129 @@
+use super::Expr;
+use crate::components::token_components::Token;
+
+#[derive(Debug)]
+pub enum Stmt<'src> {
+    Block(Vec<Stmt<'src>>),
+    Break(Token<'src>),
+    /// The class's name, the superclass it inherits from, its methods (each a `Function`) and its doc comment.
+    Class(
+        Token<'src>,
+        Option<Expr<'src>>,
+        Vec<Stmt<'src>>,
+        Option<String>,
+    ),
+    Continue(Token<'src>),
+    Expression(Expr<'src>),
+    /// The function's name, its parameters, the statements of its body and the doc comment written above it.
+    Function(
+        Token<'src>,
+        Vec<Token<'src>>,
+        Vec<Stmt<'src>>,
+        Option<String>,
+    ),
+    /// The condition, the statement run when it's true and the one run when it's false.
+    If(Expr<'src>, Box<Stmt<'src>>, Option<Box<Stmt<'src>>>),
+    Print(Expr<'src>),
+    /// The `return` keyword, kept to point errors at, and the value if there is one.
+    Return(Token<'src>, Option<Expr<'src>>),
+    /// The variable's name, its initialiser if it has one, and the doc comment written above the declaration.
+    Var(Token<'src>, Option<Expr<'src>>, Option<String>),
+    /// The only loop, `for` loops are turned into one of these by the parser. The condition, the body, and the
+    /// increment of a `for` loop, which runs after the body and after every `continue`.
+    While(Expr<'src>, Box<Stmt<'src>>, Option<Expr<'src>>),
+}
+
+impl std::fmt::Display for Stmt<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Block(statements) => {
+                write!(f, "(block")?;
+
+                for statement in statements {
+                    write!(f, " {statement}")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::Break(_) => write!(f, "(break)"),
+            Self::Class(name, superclass, methods, _) => {
+                write!(f, "(class {name}")?;
+
+                if let Some(superclass) = superclass {
+                    write!(f, " < {superclass}")?;
+                }
+
+                for method in methods {
+                    write!(f, " {method}")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::Continue(_) => write!(f, "(continue)"),
+            Self::Expression(expr) => write!(f, "(expr {expr})"),
+            Self::Function(name, params, body, _) => {
+                write!(f, "(fun {name} (")?;
+
+                for (index, param) in params.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " ")?;
+                    }
+
+                    write!(f, "{param}")?;
+                }
+
+                write!(f, ")")?;
+
+                for statement in body {
+                    write!(f, " {statement}")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::If(condition, then_branch, Some(else_branch)) => {
+                write!(f, "(if {condition} {then_branch} {else_branch})")
+            }
+            Self::If(condition, then_branch, None) => write!(f, "(if {condition} {then_branch})"),
+            Self::Print(expr) => write!(f, "(print {expr})"),
+            Self::Return(_, Some(value)) => write!(f, "(return {value})"),
+            Self::Return(_, None) => write!(f, "(return)"),
+            Self::Var(name, Some(initialiser), _) => write!(f, "(var {name} {initialiser})"),
+            Self::Var(name, None, _) => write!(f, "(var {name})"),
+            Self::While(condition, body, Some(increment)) => {
+                write!(f, "(while {condition} {body} {increment})")
+            }
+            Self::While(condition, body, None) => write!(f, "(while {condition} {body})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stmt;
+    use crate::components::{
+        parser_components::Expr,
+        token_components::{LiteralType, Span, Token, TokenType::*},
+    };
+
+    #[test]
+    fn should_stringify_block() {
+        let ast = Stmt::Block(vec![
+            Stmt::Var(
+                Token {
+                    lexeme: "a",
+                    literal: None,
+                    token: Identifier,
+                    span: Span::default(),
+                    leading_trivia: vec![],
+                    trailing_trivia: vec![],
+                },
+                Some(Expr::Literal(LiteralType::Number(1.0))),
+                None,
+            ),
+            Stmt::Print(Expr::Literal(LiteralType::Str("hi".into()))),
+            Stmt::Block(vec![]),
+        ]);
+
+        assert_eq!("(block (var a 1) (print hi) (block))", ast.to_string());
+    }
+}
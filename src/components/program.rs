@@ -1,7 +1,8 @@
 use std::process;
 
-use crate::components::Scanner;
+use crate::components::{Parser, Scanner};
 
+#[derive(Default)]
 pub struct Program {
     pub had_error: bool,
 }
@@ -14,16 +15,21 @@ impl Program {
     }
 
     pub fn run(&mut self, source: &str) {
-        // First we scan the source for its distinct tokens
-        let mut scanner = Scanner::new(source);
+        // The scanner hands the source's distinct tokens to the parser as it asks for them
+        let scanner = Scanner::new(source);
 
-        let (tokens, errors) = scanner.scan_tokens();
+        // Then we parse those tokens into statements
+        let mut parser = Parser::new(scanner);
+
+        let (statements, errors) = parser.parse();
 
         for error in errors {
             self.report(error.to_string());
         }
 
-        println!("{:?}", tokens);
+        for statement in statements {
+            println!("{statement}");
+        }
 
         self.exit();
     }
@@ -36,9 +42,3 @@ impl Program {
         process::exit(0);
     }
 }
-
-impl Default for Program {
-    fn default() -> Self {
-        Self { had_error: false }
-    }
-}
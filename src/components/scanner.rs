@@ -1,63 +1,414 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::ops::Range;
+
 use thiserror::Error;
+use unicode_ident::{is_xid_continue, is_xid_start};
 
-use super::token_components::{LiteralType, Token, TokenType, TokenType::*};
+use super::token_components::{
+    LiteralType, Span, Token, TokenType, TokenType::*, Trivia, TriviaKind,
+};
 
-pub struct Scanner {
-    source: Vec<char>,
-    tokens: Vec<Token>,
+/// Walks the source by byte index. Tokens borrow their lexemes straight from the source, so nothing is copied while scanning.
+pub struct Scanner<'src> {
+    source: &'src str,
+    tokens: Vec<Token<'src>>,
     start: usize,
     current: usize,
     line: usize,
+    column: usize,
+    start_line: usize,
+    start_column: usize,
+    /// The interpolation holes (`${ ... }`) we are currently inside of, innermost last.
+    interpolations: Vec<Interpolation>,
+    /// Set once the EOF token has been produced, after which the scanner yields nothing more.
+    finished: bool,
+    /// Whether whitespace and comments are kept as trivia on the tokens, rather than thrown away.
+    lossless: bool,
+    /// Trivia collected for the next token. It can build up over several calls when errors get in the way.
+    leading_trivia: Vec<Trivia<'src>>,
+    /// Errors found while scanning trivia or the literal of a token, handed out on the following calls, oldest first.
+    pending_errors: VecDeque<ScannerError>,
     errors: Vec<ScannerError>,
 }
 
-#[derive(Error, Debug)]
+/// An open `${` in a string literal. Braces opened inside the hole are counted so we know which `}` closes it.
+struct Interpolation {
+    start: Span,
+    open_braces: usize,
+}
+
+/// What stopped the scanner while it was reading the characters of a string.
+enum StringEnd {
+    Quote,
+    Interpolation,
+    Eof,
+}
+
+/// The scanner looks at most two characters (up to 8 bytes) past its position before deciding where the next token
+/// starts, so tokens starting this far before an edit were scanned without seeing it.
+const RELEX_LOOKAHEAD: usize = 8;
+
+/// A change to the source: the bytes in `range` of the old source replaced by `text`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit<'a> {
+    pub range: Range<usize>,
+    pub text: &'a str,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum ScannerError {
-    #[error("Unexpected EOF encountered")]
-    UnexpectedEof,
-    #[error("Unterminated string. All strings must close. Encountered on line {line}")]
-    UnterminatedString { line: usize },
-    #[error("Could not convert '{received}' into a number on line {line}")]
-    InvalidNumber { received: String, line: usize },
-    #[error("Unexpected token '{lexeme}' on line {line}")]
-    UnexpectedToken { lexeme: String, line: usize },
+    #[error("Unexpected EOF encountered on {span}")]
+    UnexpectedEof { span: Span },
+    #[error("Unterminated string. All strings must close. Encountered on {span}")]
+    UnterminatedString { span: Span },
+    #[error("Unterminated string interpolation. Every '${{' must be closed by a '}}'. Interpolation starts on {span}")]
+    UnterminatedInterpolation { span: Span },
+    #[error("Unterminated block comment. All comments must close. Comment starts on {span}")]
+    UnterminatedComment { span: Span },
+    #[error("Invalid escape sequence '{escape}' in string on {span}")]
+    InvalidEscape { escape: String, span: Span },
+    #[error("Could not convert '{received}' into a number on {span}. {reason}")]
+    InvalidNumber {
+        received: String,
+        reason: String,
+        span: Span,
+    },
+    #[error("Unexpected token '{lexeme}' on {span}")]
+    UnexpectedToken { lexeme: String, span: Span },
+}
+
+impl ScannerError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnexpectedEof { span }
+            | Self::UnterminatedString { span }
+            | Self::UnterminatedInterpolation { span }
+            | Self::UnterminatedComment { span }
+            | Self::InvalidEscape { span, .. }
+            | Self::InvalidNumber { span, .. }
+            | Self::UnexpectedToken { span, .. } => *span,
+        }
+    }
+
+    /// Whether the error swallowed the rest of the source, so nothing after it could be scanned.
+    pub fn ends_input(&self) -> bool {
+        matches!(
+            self,
+            Self::UnexpectedEof { .. }
+                | Self::UnterminatedString { .. }
+                | Self::UnterminatedInterpolation { .. }
+                | Self::UnterminatedComment { .. }
+        )
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Self::UnexpectedEof { span }
+            | Self::UnterminatedString { span }
+            | Self::UnterminatedInterpolation { span }
+            | Self::UnterminatedComment { span }
+            | Self::InvalidEscape { span, .. }
+            | Self::InvalidNumber { span, .. }
+            | Self::UnexpectedToken { span, .. } => span,
+        }
+    }
 }
 
-impl Scanner {
-    pub fn new(source: &str) -> Self {
+impl<'src> Scanner<'src> {
+    pub fn new(source: &'src str) -> Self {
         Self {
-            source: source.chars().collect(),
+            source,
             tokens: vec![],
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            interpolations: vec![],
+            finished: false,
+            lossless: false,
+            leading_trivia: vec![],
+            pending_errors: VecDeque::new(),
             errors: vec![],
         }
     }
 
-    pub fn scan_tokens(&mut self) -> (&Vec<Token>, &Vec<ScannerError>) {
-        while !self.is_at_end() {
-            self.start = self.current;
+    /// A scanner that keeps whitespace and comments as trivia on each token, and any text it reports an error for as
+    /// skipped trivia, so the source can be rebuilt from the tokens byte for byte (see `Token::write_source`).
+    pub fn new_lossless(source: &'src str) -> Self {
+        Self {
+            lossless: true,
+            ..Self::new(source)
+        }
+    }
+
+    /// Scans the whole source up front. Use the scanner as an iterator instead to get tokens on demand.
+    pub fn scan_tokens(&mut self) -> (&Vec<Token<'src>>, &Vec<ScannerError>) {
+        while let Some(token_scan_result) = self.next() {
+            match token_scan_result {
+                Ok(token) => self.tokens.push(token),
+                Err(scan_error) => self.errors.push(scan_error),
+            };
+        }
+
+        (&self.tokens, &self.errors)
+    }
+
+    /// Takes the tokens and errors the scanner produced, so they can be handed to `relex` after the next edit.
+    pub fn into_parts(self) -> (Vec<Token<'src>>, Vec<ScannerError>) {
+        (self.tokens, self.errors)
+    }
+
+    /// Scans the source again after `edit`, given the tokens and errors from scanning the source as it was before the
+    /// edit. Only the text around the edit is scanned and spliced into `tokens`, the tokens after it only have their
+    /// positions shifted, and the result is exactly what scanning the whole new source would give.
+    ///
+    /// `tokens` and `errors` must be everything a scanner in the same mode (lossless or not) produced for the old source.
+    /// The reused tokens have their text sliced from the new source, so the old source can be dropped afterwards.
+    /// Anything the scanner had already scanned is thrown away first.
+    pub fn relex(
+        &mut self,
+        mut tokens: Vec<Token<'_>>,
+        errors: Vec<ScannerError>,
+        edit: &TextEdit,
+    ) -> (&Vec<Token<'src>>, &Vec<ScannerError>) {
+        self.reset();
+
+        // The scanner can only pick up at a token outside of any interpolation.
+        let mut restart = None;
+        let mut depth = 0;
+
+        for (index, token) in tokens.iter().enumerate() {
+            if token.span.start + RELEX_LOOKAHEAD > edit.range.start {
+                break;
+            }
+
+            if depth == 0 {
+                restart = Some(index);
+            }
+
+            depth = interpolation_depth(depth, token);
+        }
+
+        // Everything before the restart token is untouched by the edit, so the scanner can pick up from there as if it
+        // had scanned that far itself. Without one we have to start from the top.
+        let restart_start = match restart {
+            Some(restart) => {
+                let token = &mut tokens[restart];
+
+                self.current = token.span.start;
+                self.line = token.span.line;
+                self.column = token.span.column;
+                self.leading_trivia =
+                    self.reuse_trivia(mem::take(&mut token.leading_trivia), |_| ());
+
+                token.span.start
+            }
+            None => 0,
+        };
 
-            let token_scan_result = self.scan_token();
+        let (head_errors, tail_errors): (Vec<_>, Vec<_>) = errors
+            .into_iter()
+            .partition(|error| error.span().start < restart_start);
 
-            if let Err(scan_error) = token_scan_result {
-                self.errors.push(scan_error);
+        self.errors = head_errors;
+
+        let restart = restart.unwrap_or(0);
+        let edit_end = edit.range.start + edit.text.len();
+        let mut old_index = restart;
+        let mut old_depth = 0;
+
+        loop {
+            // Only trivia is scanned before the next token, so this is the state the token is scanned in.
+            let outside_interpolation = self.interpolations.is_empty();
+
+            let Some(token_scan_result) = self.next() else {
+                break;
+            };
+
+            let token = match token_scan_result {
+                Ok(token) => token,
+                Err(scan_error) => {
+                    self.errors.push(scan_error);
+
+                    continue;
+                }
             };
+
+            // Once we are past the edit, and in the same state as the old scan was at the same text, the rest of the
+            // old scan can be reused.
+            if token.span.start >= edit_end && outside_interpolation {
+                let old_start = token.span.start - edit.text.len() + edit.range.len();
+
+                while tokens
+                    .get(old_index)
+                    .is_some_and(|old_token| old_token.span.start < old_start)
+                {
+                    old_depth = interpolation_depth(old_depth, &tokens[old_index]);
+                    old_index += 1;
+                }
+
+                if old_depth == 0
+                    && tokens
+                        .get(old_index)
+                        .is_some_and(|old_token| old_token.span.start == old_start)
+                {
+                    self.splice_rest(tokens, tail_errors, restart..old_index + 1, token);
+
+                    return (&self.tokens, &self.errors);
+                }
+            }
+
+            self.tokens.push(token);
         }
 
-        self.tokens.push(Token {
-            token: EOF,
-            line: self.line,
-            lexeme: "\0".into(),
-            literal: None,
-        });
+        tokens.truncate(restart);
+
+        let mut reused = self.reuse_tokens(tokens, restart, |_| ());
+
+        reused.append(&mut self.tokens);
+        self.tokens = reused;
 
         (&self.tokens, &self.errors)
     }
+    /// Finishes a re-scan by putting the freshly scanned tokens in place of the old tokens in `replaced`, where `token`
+    /// is the freshly scanned token that matches the last of them. The old tokens after them are kept, moved to their
+    /// new positions.
+    fn splice_rest(
+        &mut self,
+        mut tokens: Vec<Token<'_>>,
+        tail_errors: Vec<ScannerError>,
+        replaced: Range<usize>,
+        token: Token<'src>,
+    ) {
+        let old_span = tokens[replaced.end - 1].span;
+        let new_span = token.span;
+
+        self.tokens.push(token);
+
+        // Columns only move on the line the tokens were matched on, the lines after it start at column 1 either way.
+        let shift = |span: &mut Span| {
+            if span.line == old_span.line {
+                span.column = span.column - old_span.column + new_span.column;
+            }
+
+            span.start = span.start - old_span.start + new_span.start;
+            span.end = span.end - old_span.start + new_span.start;
+            span.line = span.line - old_span.line + new_span.line;
+        };
+
+        let tail_start = replaced.start;
+
+        tokens.drain(replaced);
+
+        let mut reused = self.reuse_tokens(tokens, tail_start, shift);
+
+        reused.splice(tail_start..tail_start, self.tokens.drain(..));
+        self.tokens = reused;
+
+        for mut error in tail_errors {
+            if error.span().start >= old_span.start {
+                shift(error.span_mut());
+
+                self.errors.push(error);
+            }
+        }
+
+        self.current = self.source.len();
+        self.finished = true;
+    }
+
+    /// Moves tokens from another scan over to this scanner's source, the ones from `tail_start` on with their spans moved
+    /// by `shift`. Their text is sliced from this scanner's source at the new spans, nothing is copied, and collecting
+    /// reuses the vector's allocation.
+    fn reuse_tokens(
+        &self,
+        tokens: Vec<Token<'_>>,
+        tail_start: usize,
+        shift: impl Fn(&mut Span),
+    ) -> Vec<Token<'src>> {
+        tokens
+            .into_iter()
+            .enumerate()
+            .map(|(index, token)| {
+                if index < tail_start {
+                    self.reuse_token(token, |_| ())
+                } else {
+                    self.reuse_token(token, &shift)
+                }
+            })
+            .collect()
+    }
+
+    fn reuse_token(&self, token: Token<'_>, shift: impl Fn(&mut Span)) -> Token<'src> {
+        let mut span = token.span;
+
+        shift(&mut span);
+
+        Token {
+            token: token.token,
+            lexeme: if token.token == EOF {
+                "\0"
+            } else {
+                self.get_source_slice(span.start, span.end)
+            },
+            literal: token.literal,
+            span,
+            leading_trivia: self.reuse_trivia(token.leading_trivia, &shift),
+            trailing_trivia: self.reuse_trivia(token.trailing_trivia, &shift),
+        }
+    }
+
+    fn reuse_trivia(
+        &self,
+        trivia: Vec<Trivia<'_>>,
+        shift: impl Fn(&mut Span),
+    ) -> Vec<Trivia<'src>> {
+        // Most tokens have no trivia outside of lossless mode, and an empty list has nothing to move over.
+        if trivia.is_empty() {
+            return vec![];
+        }
+
+        trivia
+            .into_iter()
+            .map(|trivia| {
+                let mut span = trivia.span;
+
+                shift(&mut span);
+
+                Trivia {
+                    kind: trivia.kind,
+                    text: self.get_source_slice(span.start, span.end),
+                    span,
+                }
+            })
+            .collect()
+    }
+
+    /// Puts the scanner back at the start of the source, forgetting everything it has scanned.
+    fn reset(&mut self) {
+        *self = Self {
+            lossless: self.lossless,
+            ..Self::new(self.source)
+        };
+    }
+
+    /// Marks the current position as the start of the next token.
+    fn begin_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+    }
 
-    /// The source in the scanner is a collection of chars. This will grab a slice, and create a new string.
-    fn get_source_slice(&self, start: usize, end: usize) -> String {
+    /// The span from the start of the token being scanned up to the current position.
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current, self.start_line, self.start_column)
+    }
+
+    /// Grabs a slice of the source between two byte offsets. The slice borrows from the source, nothing is allocated.
+    fn get_source_slice(&self, start: usize, end: usize) -> &'src str {
         debug_assert!(
             start != end,
             "Start index is identical to the end index when fetching the source slice"
@@ -66,98 +417,259 @@ impl Scanner {
         self.source
             .get(start..end)
             .expect("Critical error in scanning source code. Attempted to extract a slice of source with an out of bounds index")
-            .iter()
-            .collect()
     }
 
     fn peek(&self) -> Option<char> {
-        if self.is_at_end() {
-            return None;
-        };
-
-        self.source.get(self.current).copied()
+        self.source.get(self.current..)?.chars().next()
     }
 
     fn peek_next(&self) -> Option<char> {
-        self.source.get(self.current + 1).copied()
+        self.source.get(self.current..)?.chars().nth(1)
     }
 
+    /// Consumes the current character, keeping the line and column up to date.
     fn advance(&mut self) -> Option<char> {
         let current_char = self.peek()?;
 
-        self.current += 1;
+        self.current += current_char.len_utf8();
+
+        match current_char {
+            // The second half of a `\r\n`, the line was already counted at the `\r`.
+            '\n' if self.source[..self.current - 1].ends_with('\r') => (),
+            '\n' | '\r' => {
+                self.line += 1;
+                self.column = 1;
+            }
+            _ => self.column += 1,
+        };
 
         Some(current_char)
     }
 
+    /// Skips a UTF-8 byte order mark and a `#!` line at the very start of the source, so scripts can be run as
+    /// executables and saved by editors that add a BOM. In lossless mode they are kept as trivia.
+    fn skip_preamble(&mut self) {
+        self.begin_token();
+
+        if self.conditional_advance('\u{FEFF}') {
+            // The BOM is invisible in editors, so it doesn't take up a column.
+            self.column = 1;
+
+            self.push_preamble_trivia(TriviaKind::ByteOrderMark);
+        }
+
+        self.begin_token();
+
+        if self.source[self.current..].starts_with("#!") {
+            while !self.is_at_end() && !self.current_char_test(is_newline) {
+                self.advance();
+            }
+
+            self.push_preamble_trivia(TriviaKind::Shebang);
+        }
+    }
+
+    fn push_preamble_trivia(&mut self, kind: TriviaKind) {
+        if self.lossless {
+            self.leading_trivia.push(Trivia {
+                kind,
+                text: self.get_source_slice(self.start, self.current),
+                span: self.span(),
+            });
+        }
+    }
+
     /// Advances the scanner's `current` only if the test character matches the current character.
     /// Otherwise false is returned and the `current` is not advanced.
     fn conditional_advance(&mut self, test: char) -> bool {
-        if self.is_at_end() {
+        if !self.current_char_test(|c| c == test) {
             return false;
-        };
+        }
+
+        self.advance();
+
+        true
+    }
+
+    /// Skips over whitespace and comments, recording them as trivia in lossless mode. Doc comments are always recorded.
+    /// Trailing trivia stops at the end of the line, the newline and anything after it belongs to the next token.
+    /// It also stops at a doc comment, as that documents whatever comes next.
+    fn scan_trivia(&mut self, trailing: bool) -> Vec<Trivia<'src>> {
+        let mut trivia = vec![];
+
+        loop {
+            self.begin_token();
+
+            let kind = match self.peek() {
+                Some('\n' | '\r') if !trailing => {
+                    // `\r\n`, `\n` and a lone `\r` all end a line.
+                    if self.advance() == Some('\r') {
+                        self.conditional_advance('\n');
+                    }
+
+                    TriviaKind::Newline
+                }
+                Some(' ' | '\t') => {
+                    while self.current_char_test(|c| matches!(c, ' ' | '\t')) {
+                        self.advance();
+                    }
 
-        let current_char = self.peek();
-
-        if let Some(char) = current_char {
-            self.current += 1;
-
-            char == test
-        } else {
-            false
-        }
-    }
-
-    fn scan_token(&mut self) -> Result<(), ScannerError> {
-        let c = self.advance().ok_or(ScannerError::UnexpectedEof)?;
-
-        match c {
-            '(' => self.add_token(LeftParen, None),
-            ')' => self.add_token(RightParen, None),
-            '{' => self.add_token(LeftBrace, None),
-            '}' => self.add_token(RightBrace, None),
-            ',' => self.add_token(Comma, None),
-            '.' => self.add_token(Dot, None),
-            '-' => self.add_token(Minus, None),
-            '+' => self.add_token(Plus, None),
-            ';' => self.add_token(Semicolon, None),
-            '*' => self.add_token(Star, None),
-            '=' if self.conditional_advance('=') => self.add_token(EqualEqual, None),
-            '!' if self.conditional_advance('=') => self.add_token(BangEqual, None),
-            '<' if self.conditional_advance('=') => self.add_token(LessEqual, None),
-            '>' if self.conditional_advance('=') => self.add_token(GreaterEqual, None),
-            '=' => self.add_token(Equal, None),
-            '/' if self.conditional_advance('/') => {
-                // This is a comment, like this one! We'll just strip it.
-                while !self.is_at_end() && !self.current_char_test(|c| c == '\n') {
+                    TriviaKind::Whitespace
+                }
+                Some('/') if self.next_char_test(|c| c == '/') => {
+                    let is_doc_comment = self.is_at_doc_comment();
+
+                    if trailing && is_doc_comment {
+                        return trivia;
+                    }
+
+                    // This is a comment, like this one! We'll just strip it.
+                    while !self.is_at_end() && !self.current_char_test(is_newline) {
+                        self.advance();
+                    }
+
+                    if is_doc_comment {
+                        TriviaKind::DocComment
+                    } else {
+                        TriviaKind::LineComment
+                    }
+                }
+                Some('/') if self.next_char_test(|c| c == '*') => {
                     self.advance();
+                    self.advance();
+
+                    if let Err(error) = self.skip_block_comment() {
+                        self.pending_errors.push_back(error);
+                    }
+
+                    TriviaKind::BlockComment
+                }
+                _ => return trivia,
+            };
+
+            if self.lossless || kind == TriviaKind::DocComment {
+                trivia.push(Trivia {
+                    kind,
+                    text: self.get_source_slice(self.start, self.current),
+                    span: self.span(),
+                });
+            }
+        }
+    }
+
+    /// Doc comments start with exactly three slashes, `////` and beyond is an ordinary comment.
+    fn is_at_doc_comment(&self) -> bool {
+        let rest = &self.source[self.current..];
+
+        rest.starts_with("///") && !rest.starts_with("////")
+    }
+
+    /// Scans the next token, which starts at the current character.
+    fn scan_token(&mut self) -> Result<Token<'src>, ScannerError> {
+        let c = self
+            .advance()
+            .ok_or(ScannerError::UnexpectedEof { span: self.span() })?;
+
+        let token = match c {
+            '(' => self.make_token(LeftParen, None),
+            ')' => self.make_token(RightParen, None),
+            '{' => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.open_braces += 1;
+                }
+
+                self.make_token(LeftBrace, None)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(interpolation) if interpolation.open_braces == 0 => {
+                    self.scan_interpolation_continuation_token()?
+                }
+                Some(interpolation) => {
+                    interpolation.open_braces -= 1;
+
+                    self.make_token(RightBrace, None)
                 }
+                None => self.make_token(RightBrace, None),
+            },
+            '[' => self.make_token(LeftBracket, None),
+            ']' => self.make_token(RightBracket, None),
+            ',' => self.make_token(Comma, None),
+            ':' => self.make_token(Colon, None),
+            '?' => self.make_token(Question, None),
+            '.' => self.make_token(Dot, None),
+            '-' => self.make_token(Minus, None),
+            '+' => self.make_token(Plus, None),
+            ';' => self.make_token(Semicolon, None),
+            '*' => self.make_token(Star, None),
+            '=' if self.conditional_advance('=') => self.make_token(EqualEqual, None),
+            '=' if self.conditional_advance('>') => self.make_token(Arrow, None),
+            '!' if self.conditional_advance('=') => self.make_token(BangEqual, None),
+            '<' if self.conditional_advance('=') => self.make_token(LessEqual, None),
+            '>' if self.conditional_advance('=') => self.make_token(GreaterEqual, None),
+            '=' => self.make_token(Equal, None),
+            '!' => self.make_token(Bang, None),
+            '<' => self.make_token(Less, None),
+            '>' => self.make_token(Greater, None),
+            '/' => self.make_token(Slash, None),
+            '"' if self.current_char_test(|c| c == '"') && self.next_char_test(|c| c == '"') => {
+                self.scan_multiline_string_token()?
             }
-            '/' => self.add_token(Slash, None),
-            '"' => self.add_string_token()?,
-            c if c.is_digit(10) => self.add_number_token()?,
-            c if c.is_ascii_alphabetic() || c == '_' => self.add_identifier_token()?,
-            ' ' | '\r' | '\t' => (),
-            '\n' => self.line += 1,
+            '"' => self.scan_string_token()?,
+            'r' if self.current_char_test(|c| c == '"' || c == '#') => {
+                self.scan_raw_string_token()?
+            }
+            c if c.is_ascii_digit() => self.scan_number_token(),
+            c if is_xid_start(c) || c == '_' => self.scan_identifier_token(),
             c => Err(ScannerError::UnexpectedToken {
                 lexeme: c.to_string(),
-                line: self.line,
+                span: self.span(),
             })?,
         };
 
+        Ok(token)
+    }
+
+    /// Skips the rest of a block comment, the opening `/*` having already been consumed. Block comments can nest, e.g.
+    /// `/* outer /* inner */ still a comment */`, so we keep track of how deep we are.
+    fn skip_block_comment(&mut self) -> Result<(), ScannerError> {
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(ScannerError::UnterminatedComment { span: self.span() });
+            }
+
+            if self.current_char_test(|c| c == '/') && self.next_char_test(|c| c == '*') {
+                self.advance();
+                self.advance();
+
+                depth += 1;
+            } else if self.current_char_test(|c| c == '*') && self.next_char_test(|c| c == '/') {
+                self.advance();
+                self.advance();
+
+                depth -= 1;
+            } else {
+                self.advance();
+            }
+        }
+
         Ok(())
     }
 
-    fn add_identifier_token(&mut self) -> Result<(), ScannerError> {
-        while self.current_char_test(|c| c.is_ascii_alphanumeric()) {
+    /// Identifiers follow the Unicode XID rules (the same ones Rust uses), plus `_` is allowed as the first character.
+    fn scan_identifier_token(&mut self) -> Token<'src> {
+        while self.current_char_test(is_xid_continue) {
             self.advance();
         }
 
         let text = self.get_source_slice(self.start, self.current);
 
-        let token_type = match text.as_str() {
+        let token_type = match text {
             "and" => And,
+            "break" => Break,
             "class" => Class,
+            "continue" => Continue,
             "else" => Else,
             "true" => True,
             "false" => False,
@@ -175,377 +687,2055 @@ impl Scanner {
             _ => Identifier,
         };
 
-        self.add_token(token_type, None);
-
-        Ok(())
+        self.make_token(token_type, None)
     }
 
-    /// Scans the entirety of a string literal into a token and adds it to the scanner's tokens vector.
-    fn add_string_token(&mut self) -> Result<(), ScannerError> {
-        while !self.current_char_test(|c| c == '"') && !self.is_at_end() {
-            if self.current_char_test(|c| c == '\n') {
-                self.line += 1;
+    /// Scans a raw string, `r"..."`, which has no escapes or interpolation. Any number of `#` can be put between the `r`
+    /// and the opening quote, and the string then only ends at a quote followed by the same number of `#`, so
+    /// `r#"say "hi""#` can contain quotes.
+    fn scan_raw_string_token(&mut self) -> Result<Token<'src>, ScannerError> {
+        let mut hashes = 0;
+
+        while self.conditional_advance('#') {
+            hashes += 1;
+        }
+
+        if !self.conditional_advance('"') {
+            return Err(ScannerError::UnexpectedToken {
+                lexeme: self.get_source_slice(self.start, self.current).into(),
+                span: self.span(),
+            });
+        }
+
+        let contents_start = self.current;
+        let closing = format!("\"{}", "#".repeat(hashes));
+
+        while !self.source[self.current..].starts_with(&closing) {
+            if self.advance().is_none() {
+                return Err(ScannerError::UnterminatedString { span: self.span() });
             }
+        }
 
+        let value = normalise_newlines(&self.source[contents_start..self.current]);
+
+        for _ in 0..closing.len() {
             self.advance();
         }
 
-        if self.is_at_end() {
-            return Err(ScannerError::UnterminatedString { line: self.line });
+        Ok(self.make_token(Str, Some(LiteralType::Str(value))))
+    }
+
+    /// Scans a `"""` string, which can span several lines and is cleaned up with `strip_indentation`.
+    ///
+    /// `"""` strings are raw by design: backslashes and `${` are kept as written, so SQL, regexes and templates meant
+    /// for other tools can be pasted in unchanged. The catch is that the text can't contain `"""` itself, a raw string
+    /// with hashes (`r#"..."#`) can. Use an ordinary string when escapes or interpolation are wanted.
+    fn scan_multiline_string_token(&mut self) -> Result<Token<'src>, ScannerError> {
+        self.advance();
+        self.advance();
+
+        let contents_start = self.current;
+
+        while !self.source[self.current..].starts_with("\"\"\"") {
+            if self.advance().is_none() {
+                return Err(ScannerError::UnterminatedString { span: self.span() });
+            }
+        }
+
+        let value = strip_indentation(&self.source[contents_start..self.current]);
+
+        self.advance();
+        self.advance();
+        self.advance();
+
+        Ok(self.make_token(Str, Some(LiteralType::Str(value))))
+    }
+
+    /// Scans the entirety of a string literal into a token.
+    /// Escape sequences are decoded into the literal value, the lexeme keeps the raw source.
+    ///
+    /// If the string contains an interpolation, only the part up to and including the `${` is scanned here.
+    fn scan_string_token(&mut self) -> Result<Token<'src>, ScannerError> {
+        let (value, end) = self.scan_string_contents();
+
+        match end {
+            StringEnd::Quote => Ok(self.make_token(Str, Some(LiteralType::Str(value)))),
+            StringEnd::Interpolation => {
+                self.interpolations.push(Interpolation {
+                    start: self.span(),
+                    open_braces: 0,
+                });
+
+                Ok(self.make_token(InterpolationStart, Some(LiteralType::Str(value))))
+            }
+            StringEnd::Eof => Err(ScannerError::UnterminatedString { span: self.span() }),
+        }
+    }
+
+    /// Scans the rest of an interpolated string after the `}` that closes a hole, up to the next hole or the closing quote.
+    fn scan_interpolation_continuation_token(&mut self) -> Result<Token<'src>, ScannerError> {
+        let (value, end) = self.scan_string_contents();
+
+        match end {
+            StringEnd::Quote => {
+                self.interpolations.pop();
+
+                Ok(self.make_token(InterpolationEnd, Some(LiteralType::Str(value))))
+            }
+            StringEnd::Interpolation => {
+                Ok(self.make_token(InterpolationSegment, Some(LiteralType::Str(value))))
+            }
+            StringEnd::Eof => {
+                self.interpolations.pop();
+
+                Err(ScannerError::UnterminatedString { span: self.span() })
+            }
+        }
+    }
+
+    /// Reads string characters until a closing quote, the start of an interpolation or the end of the source.
+    /// An invalid escape is kept in the value as written, and the first one is handed out as an error after the token.
+    fn scan_string_contents(&mut self) -> (String, StringEnd) {
+        let mut value = String::new();
+        let mut escape_error = None;
+
+        let end = loop {
+            match self.peek() {
+                None => break StringEnd::Eof,
+                Some('"') => {
+                    self.advance();
+
+                    break StringEnd::Quote;
+                }
+                Some('$') if self.next_char_test(|c| c == '{') => {
+                    self.advance();
+                    self.advance();
+
+                    break StringEnd::Interpolation;
+                }
+                Some('\\') => {
+                    // Keep scanning to the closing quote so one bad escape doesn't derail the rest of the source.
+                    match self.scan_escape() {
+                        Ok(c) => value.push(c),
+                        Err(error) => {
+                            if let ScannerError::InvalidEscape { escape, .. } = &error {
+                                value.push_str(escape);
+                            }
+
+                            escape_error.get_or_insert(error);
+                        }
+                    }
+                }
+                // Line breaks inside strings are always read as `\n`, whatever the file uses.
+                Some('\r') => {
+                    self.advance();
+
+                    if !self.current_char_test(|c| c == '\n') {
+                        value.push('\n');
+                    }
+                }
+                Some(_) => value.extend(self.advance()),
+            }
+        };
+
+        // An unterminated string is reported instead, the string never becomes a token.
+        if let (Some(error), StringEnd::Quote | StringEnd::Interpolation) = (escape_error, &end) {
+            self.pending_errors.push_back(error);
         }
 
+        (value, end)
+    }
+
+    /// Scans a single escape sequence, starting at the backslash, and returns the character it stands for.
+    fn scan_escape(&mut self) -> Result<char, ScannerError> {
+        let escape_start = self.current;
+        let escape_span = Span::new(self.current, self.current, self.line, self.column);
+
+        // Step over the backslash.
         self.advance();
 
-        let value = self.get_source_slice(self.start + 1, self.current - 1);
+        let escaped = match self.advance() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('\\') => Some('\\'),
+            Some('"') => Some('"'),
+            Some('$') => Some('$'),
+            Some('0') => Some('\0'),
+            Some('u') => self.scan_unicode_escape(),
+            _ => None,
+        };
+
+        escaped.ok_or_else(|| ScannerError::InvalidEscape {
+            escape: self.get_source_slice(escape_start, self.current).into(),
+            span: Span {
+                end: self.current,
+                ..escape_span
+            },
+        })
+    }
+
+    /// Scans the `{XXXX}` part of a `\u{XXXX}` escape. Only the braces and hex digits are consumed, so a
+    /// malformed escape never swallows the closing quote of the string.
+    fn scan_unicode_escape(&mut self) -> Option<char> {
+        if !self.conditional_advance('{') {
+            return None;
+        }
+
+        let mut digits = String::new();
+
+        while self.current_char_test(|c| c.is_ascii_hexdigit()) {
+            digits.extend(self.advance());
+        }
+
+        if digits.is_empty() || digits.len() > 6 || !self.conditional_advance('}') {
+            return None;
+        }
+
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+    }
+
+    /// Scans a number literal. Supports decimals with an optional fraction and exponent (`1.5e-3`),
+    /// `0x`, `0b` and `0o` prefixed integers, and `_` digit separators (`1_000_000`).
+    ///
+    /// A malformed number still becomes a token, so the parser doesn't trip over the same mistake. Its value is 0 and
+    /// the error is handed out after it.
+    fn scan_number_token(&mut self) -> Token<'src> {
+        let prefixed_radix = match (self.source.as_bytes()[self.start], self.peek()) {
+            (b'0', Some('x' | 'X')) => Some((16, "hexadecimal")),
+            (b'0', Some('b' | 'B')) => Some((2, "binary")),
+            (b'0', Some('o' | 'O')) => Some((8, "octal")),
+            _ => None,
+        };
+
+        let value = match prefixed_radix {
+            Some((radix, name)) => {
+                self.advance();
+
+                self.scan_prefixed_number(radix, name)
+            }
+            None => self.scan_decimal_number(),
+        };
+
+        let value = value.unwrap_or_else(|error| {
+            self.pending_errors.push_back(error);
+
+            0.0
+        });
+
+        self.make_token(Number, Some(LiteralType::Number(value)))
+    }
+
+    fn scan_decimal_number(&mut self) -> Result<f64, ScannerError> {
+        self.skip_digits();
+
+        if self.current_char_test(|c| c == '.') && self.next_char_test(|c| c.is_ascii_digit()) {
+            self.advance();
+            self.skip_digits();
+        }
+
+        if self.current_char_test(|c| c == 'e' || c == 'E') {
+            self.advance();
+
+            if self.current_char_test(|c| c == '+' || c == '-') {
+                self.advance();
+            }
+
+            if !self.current_char_test(|c| c.is_ascii_digit()) {
+                return Err(self.invalid_number("Expected digits after the exponent"));
+            }
+
+            self.skip_digits();
+        }
+
+        let source_slice = self.get_source_slice(self.start, self.current);
+
+        for digits in source_slice.split(['.', 'e', 'E', '+', '-']) {
+            self.check_digit_separators(digits)?;
+        }
+
+        // Integers are held to the same standard as prefixed ones. Fractions and exponents are rounded like any float.
+        if source_slice
+            .bytes()
+            .all(|c| c.is_ascii_digit() || c == b'_')
+        {
+            return self.exact_integer(source_slice, 10, "decimal");
+        }
+
+        let value: f64 = source_slice
+            .replace('_', "")
+            .parse()
+            .map_err(|_| self.invalid_number("Not a valid decimal number"))?;
+
+        if !value.is_finite() {
+            return Err(self.invalid_number("The number is too large to be represented"));
+        }
+
+        Ok(value)
+    }
+
+    /// Scans the digits following a `0x`, `0b` or `0o` prefix, which has already been consumed.
+    fn scan_prefixed_number(&mut self, radix: u32, name: &str) -> Result<f64, ScannerError> {
+        let digits_start = self.current;
+
+        // Take everything that could be part of the literal, so `0b102` is reported as one bad number rather than two tokens.
+        while self.current_char_test(|c| c.is_ascii_alphanumeric() || c == '_') {
+            self.advance();
+        }
+
+        let digits = &self.source[digits_start..self.current];
+
+        if let Some(invalid) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+            return Err(self.invalid_number(format!("'{invalid}' is not a valid {name} digit")));
+        }
+
+        if digits.is_empty() {
+            return Err(self.invalid_number(format!("Expected {name} digits after the prefix")));
+        }
+
+        self.check_digit_separators(digits)?;
+
+        self.exact_integer(digits, radix, name)
+    }
+
+    /// Converts integer digits (separators allowed) into a number, as long as the number holds the value exactly rather
+    /// than rounding it.
+    fn exact_integer(&self, digits: &str, radix: u32, name: &str) -> Result<f64, ScannerError> {
+        // The exact value as little-endian 32 bit limbs, so literals of any size can be checked. The top limb is never 0.
+        let mut limbs: Vec<u32> = vec![];
+
+        for digit in digits.chars().filter_map(|c| c.to_digit(radix)) {
+            let mut carry = u64::from(digit);
+
+            for limb in &mut limbs {
+                let product = u64::from(*limb) * u64::from(radix) + carry;
+
+                *limb = product as u32;
+                carry = product >> 32;
+            }
+
+            if carry != 0 {
+                limbs.push(carry as u32);
+            }
+        }
+
+        let (Some(lowest), Some(highest)) =
+            (limbs.iter().position(|limb| *limb != 0), limbs.last())
+        else {
+            return Ok(0.0);
+        };
+
+        let bit_length = limbs.len() as u32 * 32 - highest.leading_zeros();
+        let trailing_zeros = lowest as u32 * 32 + limbs[lowest].trailing_zeros();
+
+        // Exact as long as the bits between the highest and lowest set bit fit in the mantissa, e.g. 2^64 or 10^20.
+        if bit_length - trailing_zeros > f64::MANTISSA_DIGITS {
+            return Err(self.invalid_number(format!(
+                "The {name} value can't be represented exactly by a number, it would be rounded"
+            )));
+        }
+
+        if bit_length > f64::MAX_EXP as u32 {
+            return Err(self.invalid_number("The number is too large to be represented"));
+        }
+
+        // Every partial sum holds a prefix of the value's bits, so none of them round.
+        Ok(limbs.iter().rev().fold(0.0, |value, limb| {
+            value * 4_294_967_296.0 + f64::from(*limb)
+        }))
+    }
+
+    fn skip_digits(&mut self) {
+        while self.current_char_test(|c| c.is_ascii_digit() || c == '_') {
+            self.advance();
+        }
+    }
+
+    /// Digit separators are only allowed between two digits, e.g. `1_000` but not `1_` or `1__0`.
+    fn check_digit_separators(&self, digits: &str) -> Result<(), ScannerError> {
+        if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            return Err(self.invalid_number("Digit separators ('_') must be placed between digits"));
+        }
+
+        Ok(())
+    }
+
+    fn invalid_number(&self, reason: impl Into<String>) -> ScannerError {
+        ScannerError::InvalidNumber {
+            received: self.get_source_slice(self.start, self.current).into(),
+            reason: reason.into(),
+            span: self.span(),
+        }
+    }
+
+    /// Creates a token for the lexeme between `start` and `current`.
+    fn make_token(&self, token_type: TokenType, literal: Option<LiteralType>) -> Token<'src> {
+        Token {
+            token: token_type,
+            lexeme: self.get_source_slice(self.start, self.current),
+            literal,
+            span: self.span(),
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.source.len()
+    }
+
+    fn current_char_test<F>(&self, test: F) -> bool
+    where
+        F: FnOnce(char) -> bool,
+    {
+        let current = self.peek();
+
+        match current {
+            Some(char) => test(char),
+            None => false,
+        }
+    }
+
+    fn next_char_test<F>(&self, test: F) -> bool
+    where
+        F: FnOnce(char) -> bool,
+    {
+        let current = self.peek_next();
+
+        match current {
+            Some(char) => test(char),
+            None => false,
+        }
+    }
+}
+
+/// How many interpolations the scanner is inside of after `token`, given how many it was inside of before it.
+fn interpolation_depth(depth: usize, token: &Token) -> usize {
+    match token.token {
+        InterpolationStart => depth + 1,
+        InterpolationEnd => depth.saturating_sub(1),
+        _ => depth,
+    }
+}
+
+fn is_newline(c: char) -> bool {
+    c == '\n' || c == '\r'
+}
+
+/// Turns `\r\n` and lone `\r` line breaks into `\n`.
+fn normalise_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Tidies up the contents of a multi-line string so it can be indented along with the code around it:
+/// - a blank first line (the line break straight after the opening quotes) is dropped, as is a blank last line,
+/// - the indentation shared by every non-blank line is removed. Tabs and spaces are not interchangeable, only the
+///   whitespace every line starts with byte for byte counts as shared. If the text starts on the same line as the
+///   opening quotes, that first line is left alone and doesn't count towards the shared indentation.
+fn strip_indentation(contents: &str) -> String {
+    let contents = normalise_newlines(contents);
+    let mut lines: Vec<&str> = contents.lines().collect();
+
+    let is_blank = |line: &&str| line.trim().is_empty();
+
+    let first_line = match lines.first() {
+        Some(line) if !is_blank(line) => Some(lines.remove(0)),
+        Some(_) => {
+            lines.remove(0);
+
+            None
+        }
+        None => None,
+    };
+
+    if lines.last().is_some_and(is_blank) {
+        lines.pop();
+    }
+
+    let common_indentation = lines
+        .iter()
+        .filter(|line| !is_blank(line))
+        .map(|line| indentation(line))
+        .reduce(|common, indentation| {
+            let shared = common
+                .bytes()
+                .zip(indentation.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            &common[..shared]
+        })
+        .unwrap_or("")
+        .len();
+
+    first_line
+        .into_iter()
+        .chain(lines.iter().map(|line| {
+            if is_blank(line) {
+                ""
+            } else {
+                &line[common_indentation..]
+            }
+        }))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The spaces and tabs a line starts with.
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Produces tokens on demand, ending with a single EOF token. Errors are yielded in place and scanning carries on after them.
+/// A string or number with a mistake in its literal is still yielded as a token, followed by the error.
+impl<'src> Iterator for Scanner<'src> {
+    type Item = Result<Token<'src>, ScannerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current == 0 {
+            self.skip_preamble();
+        }
+
+        if let Some(scan_error) = self.pending_errors.pop_front() {
+            return Some(Err(scan_error));
+        }
+
+        let mut leading_trivia = self.scan_trivia(false);
+
+        self.leading_trivia.append(&mut leading_trivia);
+
+        if let Some(scan_error) = self.pending_errors.pop_front() {
+            return Some(Err(scan_error));
+        }
+
+        if !self.is_at_end() {
+            self.begin_token();
+
+            return match self.scan_token() {
+                Ok(mut token) => {
+                    token.leading_trivia = mem::take(&mut self.leading_trivia);
+                    token.trailing_trivia = self.scan_trivia(true);
+
+                    Some(Ok(token))
+                }
+                Err(scan_error) => {
+                    if self.lossless {
+                        self.leading_trivia.push(Trivia {
+                            kind: TriviaKind::Skipped,
+                            text: self.get_source_slice(self.start, self.current),
+                            span: self.span(),
+                        });
+                    }
+
+                    Some(Err(scan_error))
+                }
+            };
+        }
+
+        if !self.interpolations.is_empty() {
+            let interpolation = self.interpolations.remove(0);
+
+            return Some(Err(ScannerError::UnterminatedInterpolation {
+                span: interpolation.start,
+            }));
+        }
+
+        if self.finished {
+            return None;
+        }
+
+        self.finished = true;
+        self.begin_token();
+
+        Some(Ok(Token {
+            token: EOF,
+            lexeme: "\0",
+            literal: None,
+            span: self.span(),
+            leading_trivia: mem::take(&mut self.leading_trivia),
+            trailing_trivia: vec![],
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use proptest::prelude::*;
+
+    use super::{
+        LiteralType, Scanner, ScannerError, Span, TextEdit, Token, TokenType::*, Trivia, TriviaKind,
+    };
+
+    #[test]
+    fn should_init_scanner() {
+        let scanner = Scanner::new("Hello, world!");
+
+        assert_eq!(scanner.source, "Hello, world!");
+    }
+
+    #[test]
+    fn is_at_end_false_on_init() {
+        let scanner = Scanner::new("Hello, world!");
+
+        assert!(!scanner.is_at_end());
+    }
+
+    #[test]
+    fn is_at_end_true_when_at_end() {
+        let mut scanner = Scanner::new("Hello, world!");
+
+        scanner.current = 13;
+
+        assert!(scanner.is_at_end());
+    }
+
+    #[test]
+    fn is_at_end_false_when_not_at_end() {
+        let mut scanner = Scanner::new("Hello, world!");
+
+        scanner.current = 12;
+
+        assert!(!scanner.is_at_end());
+    }
+
+    #[test]
+    fn should_return_error_on_unknown_token() {
+        let mut scanner = Scanner::new("#");
+
+        let result = scanner
+            .scan_token()
+            .expect_err("Unexpected successful scan");
+
+        assert!(matches!(
+            result,
+            ScannerError::UnexpectedToken { lexeme: _, span: _ }
+        ));
+    }
+
+    #[test]
+    fn should_return_unexpected_eof() {
+        let mut scanner = Scanner::new("");
+
+        scanner.current = 1;
+
+        let result = scanner.scan_token();
+
+        assert!(matches!(
+            result,
+            Err(ScannerError::UnexpectedEof { span: _ })
+        ));
+    }
+
+    #[test]
+    fn should_make_a_token() {
+        let mut scanner = Scanner::new("=");
+
+        // This would be handled automatically when iterating, but for testing purposes we need to set the start and current manually.
+        scanner.current += 1;
+
+        let token = scanner.make_token(LeftParen, None);
+
+        assert_eq!(
+            vec![token],
+            vec![Token {
+                token: LeftParen,
+                literal: None,
+                lexeme: "=",
+                span: Span::new(0, 1, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }]
+        )
+    }
+
+    #[test]
+    fn should_add_two_char_token() {
+        let mut scanner = Scanner::new("<=");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: LessEqual,
+                literal: None,
+                lexeme: "<=",
+                span: Span::new(0, 2, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn should_add_conditional_tokens() {
+        let mut scanner = Scanner::new("a ? b : c");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens.iter().map(|token| token.token).collect::<Vec<_>>(),
+            vec![Identifier, Question, Identifier, Colon, Identifier, EOF]
+        );
+    }
+
+    #[test]
+    fn should_add_bracket_tokens() {
+        let mut scanner = Scanner::new("xs[[1]]");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens.iter().map(|token| token.token).collect::<Vec<_>>(),
+            vec![
+                Identifier,
+                LeftBracket,
+                LeftBracket,
+                Number,
+                RightBracket,
+                RightBracket,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn should_add_loop_control_keywords() {
+        let mut scanner = Scanner::new("break continue breaks");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens.iter().map(|token| token.token).collect::<Vec<_>>(),
+            vec![Break, Continue, Identifier, EOF]
+        );
+    }
+
+    #[test]
+    fn should_add_arrow_token() {
+        let mut scanner = Scanner::new("() => a >= b == c");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens.iter().map(|token| token.token).collect::<Vec<_>>(),
+            vec![
+                LeftParen,
+                RightParen,
+                Arrow,
+                Identifier,
+                GreaterEqual,
+                Identifier,
+                EqualEqual,
+                Identifier,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn should_add_eof_after_scan() {
+        let mut scanner = Scanner::new("");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: EOF,
+                literal: None,
+                lexeme: "\0",
+                span: Span::new(0, 0, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn should_strip_comments() {
+        let mut scanner = Scanner::new("// this is a comment");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: EOF,
+                literal: None,
+                lexeme: "\0",
+                span: Span::new(20, 20, 1, 21),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+        assert!(tokens.len() == 1);
+    }
+
+    #[test]
+    fn should_match_tokens_prior_to_comment() {
+        let mut scanner = Scanner::new("<= // Wow! A less-than-or-equal-to binary operator!");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: LessEqual,
+                literal: None,
+                lexeme: "<=",
+                span: Span::new(0, 2, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+        assert!(tokens.len() == 2); // Includes EOF
+    }
+
+    #[test]
+    fn should_increment_line() {
+        let mut scanner = Scanner::new("(\n()\n+//\r\n");
+
+        scanner.scan_tokens();
+
+        assert_eq!(scanner.line, 4);
+    }
+
+    #[test]
+    fn should_ignore_whitespace() {
+        let mut scanner = Scanner::new("\t   \t  \t");
+
+        scanner.scan_tokens();
+
+        assert_eq!(scanner.tokens.len(), 1); // Includes EOF
+    }
+
+    #[test]
+    fn should_scan_string() {
+        let mut scanner = Scanner::new("\"Hello, world!\"");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: Str,
+                literal: Some(LiteralType::Str("Hello, world!".into())),
+                lexeme: "\"Hello, world!\"",
+                span: Span::new(0, 15, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn should_error_on_unterminated_string() {
+        let mut scanner = Scanner::new("\"Hello, world!");
+
+        let (_, errors) = scanner.scan_tokens();
+
+        assert!(matches!(
+            errors[0],
+            ScannerError::UnterminatedString {
+                span: Span { line: 1, .. }
+            }
+        ));
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn should_convert_a_string_to_number() {
+        let mut scanner = Scanner::new("3.14");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: Number,
+                literal: Some(LiteralType::Number(3.14)),
+                lexeme: "3.14",
+                span: Span::new(0, 4, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn should_convert_string_to_number_int() {
+        let mut scanner = Scanner::new("3");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: Number,
+                literal: Some(LiteralType::Number(3.0)),
+                lexeme: "3",
+                span: Span::new(0, 1, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn should_add_identifier() {
+        let mut scanner = Scanner::new("while");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: While,
+                literal: None,
+                lexeme: "while",
+                span: Span::new(0, 5, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn should_add_identifier_with_underscore() {
+        let mut scanner = Scanner::new("_random");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(
+            tokens[0],
+            Token {
+                token: Identifier,
+                literal: None,
+                lexeme: "_random",
+                span: Span::new(0, 7, 1, 1),
+                leading_trivia: vec![],
+                trailing_trivia: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn should_gracefully_continue_scanning_even_of_error() {
+        let mut scanner = Scanner::new("() {} # $ \"hi");
+
+        let (_, errors) = scanner.scan_tokens();
+
+        assert!(errors.len() == 3);
+    }
+
+    #[test]
+    fn should_track_span_across_lines() {
+        let mut scanner = Scanner::new("(\n  \"é\" +");
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[1].span, Span::new(4, 8, 2, 3));
+        assert_eq!(tokens[2].span, Span::new(9, 10, 2, 7));
+        assert_eq!(tokens[3].span, Span::new(10, 10, 2, 8));
+    }
+
+    #[test]
+    fn should_report_error_span() {
+        let mut scanner = Scanner::new("+\n  # \"hi\nthere");
+
+        let (_, errors) = scanner.scan_tokens();
+
+        assert!(matches!(
+            errors[0],
+            ScannerError::UnexpectedToken {
+                lexeme: _,
+                span: Span {
+                    start: 4,
+                    end: 5,
+                    line: 2,
+                    column: 3
+                }
+            }
+        ));
+        assert!(matches!(
+            errors[1],
+            ScannerError::UnterminatedString {
+                span: Span {
+                    start: 6,
+                    end: 15,
+                    line: 2,
+                    column: 5
+                }
+            }
+        ));
+    }
+
+    #[test]
+    fn should_strip_block_comments() {
+        let mut scanner = Scanner::new("( /* a comment\nspanning lines */ )");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(tokens.len(), 3); // Includes EOF
+        assert_eq!(tokens[1].token, RightParen);
+        assert_eq!(tokens[1].span, Span::new(33, 34, 2, 19));
+    }
+
+    #[test]
+    fn should_strip_nested_block_comments() {
+        let mut scanner = Scanner::new("/* outer /* inner */ still outer */ +");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].token, Plus);
+        assert_eq!(tokens.len(), 2); // Includes EOF
+    }
+
+    #[test]
+    fn should_increment_line_in_block_comment() {
+        let mut scanner = Scanner::new("/*\n/*\n*/\n*/");
+
+        scanner.scan_tokens();
+
+        assert_eq!(scanner.line, 4);
+    }
+
+    #[test]
+    fn should_error_on_unterminated_block_comment() {
+        let mut scanner = Scanner::new("+\n  /* outer /* inner */\n");
+
+        let (_, errors) = scanner.scan_tokens();
+
+        assert!(matches!(
+            errors[0],
+            ScannerError::UnterminatedComment {
+                span: Span {
+                    start: 4,
+                    line: 2,
+                    column: 3,
+                    ..
+                }
+            }
+        ));
+    }
+
+    #[test]
+    fn should_decode_escapes() {
+        let mut scanner = Scanner::new(r#""a\"b\n\t\r\\\0\u{1F600}\u{e9}""#);
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens[0].literal,
+            Some(LiteralType::Str("a\"b\n\t\r\\\0😀é".into()))
+        );
+        assert_eq!(tokens[0].lexeme, r#""a\"b\n\t\r\\\0\u{1F600}\u{e9}""#);
+    }
+
+    #[test]
+    fn should_error_on_unknown_escape() {
+        let mut scanner = Scanner::new(r#""ab\qc" +"#);
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(matches!(
+            &errors[0],
+            ScannerError::InvalidEscape {
+                escape,
+                span: Span {
+                    start: 3,
+                    end: 5,
+                    line: 1,
+                    column: 4
+                }
+            } if escape == r"\q"
+        ));
+        assert_eq!(tokens[0].token, Str);
+        assert_eq!(tokens[0].literal, Some(LiteralType::Str(r"ab\qc".into())));
+        assert_eq!(tokens[1].token, Plus);
+    }
+
+    #[test]
+    fn should_error_on_malformed_unicode_escape() {
+        let mut scanner = Scanner::new(r#""\u{110000}" "\u{}" "\u12" "\u{12""#);
+
+        let (_, errors) = scanner.scan_tokens();
+
+        assert_eq!(errors.len(), 4);
+        assert!(errors
+            .iter()
+            .all(|error| matches!(error, ScannerError::InvalidEscape { .. })));
+    }
+
+    #[test]
+    fn should_scan_interpolation() {
+        let mut scanner = Scanner::new(r#""Hello ${name}, you have ${count + 1} items""#);
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens.iter().map(|token| token.token).collect::<Vec<_>>(),
+            vec![
+                InterpolationStart,
+                Identifier,
+                InterpolationSegment,
+                Identifier,
+                Plus,
+                Number,
+                InterpolationEnd,
+                EOF
+            ]
+        );
+        assert_eq!(tokens[0].lexeme, "\"Hello ${");
+        assert_eq!(tokens[0].literal, Some(LiteralType::Str("Hello ".into())));
+        assert_eq!(tokens[2].lexeme, "}, you have ${");
+        assert_eq!(
+            tokens[2].literal,
+            Some(LiteralType::Str(", you have ".into()))
+        );
+        assert_eq!(tokens[6].lexeme, "} items\"");
+        assert_eq!(tokens[6].literal, Some(LiteralType::Str(" items".into())));
+    }
+
+    #[test]
+    fn should_scan_braces_and_strings_inside_interpolation() {
+        let mut scanner = Scanner::new(r#""a ${ {} "b ${c}" } d" {}"#);
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens.iter().map(|token| token.token).collect::<Vec<_>>(),
+            vec![
+                InterpolationStart,
+                LeftBrace,
+                RightBrace,
+                InterpolationStart,
+                Identifier,
+                InterpolationEnd,
+                InterpolationEnd,
+                LeftBrace,
+                RightBrace,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn should_not_interpolate_escaped_dollar() {
+        let mut scanner = Scanner::new(r#""\${a}""#);
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        assert_eq!(tokens[0].token, Str);
+        assert_eq!(tokens[0].literal, Some(LiteralType::Str("${a}".into())));
+    }
+
+    #[test]
+    fn should_error_on_unterminated_interpolation() {
+        let mut scanner = Scanner::new(r#""a ${b"#);
+
+        let (_, errors) = scanner.scan_tokens();
+
+        assert!(matches!(
+            errors[0],
+            ScannerError::UnterminatedInterpolation {
+                span: Span {
+                    start: 0,
+                    end: 5,
+                    ..
+                }
+            }
+        ));
+    }
+
+    #[test]
+    fn should_convert_prefixed_numbers() {
+        let mut scanner = Scanner::new(
+            "0x1F 0XfF 0b1010 0o755 0xFFFF_FFFF 0x20_0000_0000_0000 0x20_0000_0000_0002 0x8000_0000_0000_0000 \
+             0x1_0000_0000_0000_0000 0xFFFF_FFFF_FFFF_F800",
+        );
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens
+                .iter()
+                .filter_map(|token| token.literal.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                LiteralType::Number(31.0),
+                LiteralType::Number(255.0),
+                LiteralType::Number(10.0),
+                LiteralType::Number(493.0),
+                LiteralType::Number(4294967295.0),
+                LiteralType::Number(9007199254740992.0),
+                LiteralType::Number(9007199254740994.0),
+                LiteralType::Number(9223372036854775808.0),
+                LiteralType::Number(18446744073709551616.0),
+                LiteralType::Number(18446744073709549568.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_convert_exponents_and_separators() {
+        let mut scanner = Scanner::new(
+            "1.5e-3 2E+2 3e2 1_000_000 1_0.2_5 9007199254740994 100000000000000000000 18446744073709551616",
+        );
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens
+                .iter()
+                .filter_map(|token| token.literal.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                LiteralType::Number(0.0015),
+                LiteralType::Number(200.0),
+                LiteralType::Number(300.0),
+                LiteralType::Number(1000000.0),
+                LiteralType::Number(10.25),
+                LiteralType::Number(9007199254740994.0),
+                LiteralType::Number(1e20),
+                LiteralType::Number(18446744073709551616.0),
+            ]
+        );
+        assert_eq!(tokens[3].lexeme, "1_000_000");
+    }
+
+    #[test]
+    fn should_error_on_invalid_numbers() {
+        let cases = [
+            ("0x", "0x", "Expected hexadecimal digits after the prefix"),
+            ("0b102", "0b102", "'2' is not a valid binary digit"),
+            ("0o8", "0o8", "'8' is not a valid octal digit"),
+            ("1e", "1e", "Expected digits after the exponent"),
+            ("1e+;", "1e+", "Expected digits after the exponent"),
+            (
+                "1_",
+                "1_",
+                "Digit separators ('_') must be placed between digits",
+            ),
+            (
+                "1__0",
+                "1__0",
+                "Digit separators ('_') must be placed between digits",
+            ),
+            (
+                "0x_1",
+                "0x_1",
+                "Digit separators ('_') must be placed between digits",
+            ),
+            (
+                "0x1_0000_0000_0000_0001",
+                "0x1_0000_0000_0000_0001",
+                "The hexadecimal value can't be represented exactly by a number, it would be rounded",
+            ),
+            (
+                "0xFFFF_FFFF_FFFF_FFFF",
+                "0xFFFF_FFFF_FFFF_FFFF",
+                "The hexadecimal value can't be represented exactly by a number, it would be rounded",
+            ),
+            (
+                "0x20_0000_0000_0001",
+                "0x20_0000_0000_0001",
+                "The hexadecimal value can't be represented exactly by a number, it would be rounded",
+            ),
+            (
+                "9007199254740993",
+                "9007199254740993",
+                "The decimal value can't be represented exactly by a number, it would be rounded",
+            ),
+            (
+                "99_999_999_999_999_999_999",
+                "99_999_999_999_999_999_999",
+                "The decimal value can't be represented exactly by a number, it would be rounded",
+            ),
+            ("1e999", "1e999", "The number is too large to be represented"),
+            (
+                "0x1_0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+                "0x1_0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+                "The number is too large to be represented",
+            ),
+        ];
+
+        for (source, expected_received, expected_reason) in cases {
+            let mut scanner = Scanner::new(source);
+
+            let (tokens, errors) = scanner.scan_tokens();
+
+            assert!(
+                matches!(
+                    &errors[0],
+                    ScannerError::InvalidNumber { received, reason, span: Span { start: 0, .. } }
+                        if received == expected_received && reason == expected_reason
+                ),
+                "Unexpected error for {source}: {:?}",
+                errors[0]
+            );
+            assert_eq!(tokens[0].lexeme, expected_received);
+            assert_eq!(tokens[0].literal, Some(LiteralType::Number(0.0)));
+        }
+    }
+
+    #[test]
+    fn should_add_identifier_with_inner_underscore() {
+        let mut scanner = Scanner::new("foo_bar __init__ a1_b2");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| (token.token, token.lexeme))
+                .collect::<Vec<_>>(),
+            vec![
+                (Identifier, "foo_bar"),
+                (Identifier, "__init__"),
+                (Identifier, "a1_b2"),
+                (EOF, "\0")
+            ]
+        );
+    }
+
+    #[test]
+    fn should_add_unicode_identifiers() {
+        let mut scanner = Scanner::new("naïve + größe_2 - переменная * 変数 / مُتَغَيِّر + ñandú_αβγ");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|token| token.token == Identifier)
+                .map(|token| token.lexeme)
+                .collect::<Vec<_>>(),
+            vec![
+                "naïve",
+                "größe_2",
+                "переменная",
+                "変数",
+                "مُتَغَيِّر",
+                "ñandú_αβγ"
+            ]
+        );
+        assert_eq!(tokens[4].span, Span::new(21, 41, 1, 19));
+    }
+
+    #[test]
+    fn should_reject_non_identifier_symbols() {
+        let mut scanner = Scanner::new("a😀b ١٢");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(matches!(
+            &errors[0],
+            ScannerError::UnexpectedToken { lexeme, .. } if lexeme == "😀"
+        ));
+        // Arabic-Indic digits may continue an identifier but not start one.
+        assert!(matches!(
+            &errors[1],
+            ScannerError::UnexpectedToken { lexeme, .. } if lexeme == "١"
+        ));
+        assert_eq!(tokens[0].lexeme, "a");
+        assert_eq!(tokens[1].lexeme, "b");
+    }
+
+    #[test]
+    fn should_borrow_lexemes_from_source() {
+        let source = String::from("var größe = \"a ${b}\";");
+        let mut scanner = Scanner::new(&source);
+
+        let (tokens, _) = scanner.scan_tokens();
+
+        for token in tokens.iter().filter(|token| token.token != EOF) {
+            assert_eq!(token.lexeme, &source[token.span.start..token.span.end]);
+            assert!(std::ptr::eq(
+                token.lexeme,
+                &source[token.span.start..token.span.end]
+            ));
+        }
+    }
+
+    #[test]
+    fn should_produce_tokens_on_demand() {
+        let mut scanner = Scanner::new("1 # 2");
+
+        assert!(matches!(
+            scanner.next(),
+            Some(Ok(Token { token: Number, .. }))
+        ));
+        assert!(scanner.current < scanner.source.len());
+        assert!(matches!(
+            scanner.next(),
+            Some(Err(ScannerError::UnexpectedToken { .. }))
+        ));
+        assert!(matches!(
+            scanner.next(),
+            Some(Ok(Token { token: Number, .. }))
+        ));
+        assert!(matches!(scanner.next(), Some(Ok(Token { token: EOF, .. }))));
+        assert!(scanner.next().is_none());
+        assert!(scanner.next().is_none());
+    }
+
+    #[test]
+    fn should_report_unterminated_interpolation_when_iterating() {
+        let results: Vec<_> = Scanner::new("\"a ${b").collect();
+
+        assert!(matches!(
+            results.as_slice(),
+            [
+                Ok(Token {
+                    token: InterpolationStart,
+                    ..
+                }),
+                Ok(Token {
+                    token: Identifier,
+                    ..
+                }),
+                Err(ScannerError::UnterminatedInterpolation { .. }),
+                Ok(Token { token: EOF, .. })
+            ]
+        ));
+    }
+
+    #[test]
+    fn should_keep_trivia_in_lossless_mode() {
+        let mut scanner = Scanner::new_lossless("a /* b */ // c\n\t+ d");
 
-        self.add_token(Str, Some(LiteralType::Str(value)));
+        let (tokens, _) = scanner.scan_tokens();
 
-        Ok(())
+        assert_eq!(tokens[0].leading_trivia, vec![]);
+        assert_eq!(
+            tokens[0].trailing_trivia,
+            vec![
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: " ",
+                    span: Span::new(1, 2, 1, 2)
+                },
+                Trivia {
+                    kind: TriviaKind::BlockComment,
+                    text: "/* b */",
+                    span: Span::new(2, 9, 1, 3)
+                },
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: " ",
+                    span: Span::new(9, 10, 1, 10)
+                },
+                Trivia {
+                    kind: TriviaKind::LineComment,
+                    text: "// c",
+                    span: Span::new(10, 14, 1, 11)
+                },
+            ]
+        );
+        assert_eq!(
+            tokens[1].leading_trivia,
+            vec![
+                Trivia {
+                    kind: TriviaKind::Newline,
+                    text: "\n",
+                    span: Span::new(14, 15, 1, 15)
+                },
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: "\t",
+                    span: Span::new(15, 16, 2, 1)
+                },
+            ]
+        );
+        assert_eq!(tokens[1].span, Span::new(16, 17, 2, 2));
     }
 
-    fn add_number_token(&mut self) -> Result<(), ScannerError> {
-        while self.current_char_test(|c| c.is_digit(10)) {
-            self.advance();
-        }
+    #[test]
+    fn should_not_keep_trivia_by_default() {
+        let mut scanner = Scanner::new("a /* b */ // c\n\t+ d");
 
-        if self.current_char_test(|c| c == '.') && self.next_char_test(|c| c.is_digit(10)) {
-            self.advance();
+        let (tokens, _) = scanner.scan_tokens();
 
-            while self.current_char_test(|c| c.is_digit(10)) {
-                self.advance();
-            }
-        }
+        assert!(tokens
+            .iter()
+            .all(|token| token.leading_trivia.is_empty() && token.trailing_trivia.is_empty()));
+    }
 
-        let source_slice = self.get_source_slice(self.start, self.current);
-        let source_slice_f = source_slice
-            .parse()
-            .map_err(|_| ScannerError::InvalidNumber {
-                received: source_slice,
-                line: self.line,
-            })?;
+    #[test]
+    fn should_keep_text_with_errors_as_skipped_trivia() {
+        let mut scanner = Scanner::new_lossless("a # \"b");
 
-        self.add_token(Number, Some(LiteralType::Number(source_slice_f)));
+        let (tokens, errors) = scanner.scan_tokens();
 
-        Ok(())
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            tokens[1]
+                .leading_trivia
+                .iter()
+                .map(|trivia| (trivia.kind, trivia.text))
+                .collect::<Vec<_>>(),
+            vec![
+                (TriviaKind::Skipped, "#"),
+                (TriviaKind::Whitespace, " "),
+                (TriviaKind::Skipped, "\"b")
+            ]
+        );
+        assert_eq!(tokens[1].token, EOF);
     }
 
-    /// Adds the token to the tokens vector.
-    /// Always advances the `current`.
-    fn add_token(&mut self, token_type: TokenType, literal: Option<LiteralType>) {
-        let lexeme = self.get_source_slice(self.start, self.current);
+    /// Rebuilds the source from the tokens of a lossless scan.
+    fn rebuild_source(source: &str) -> String {
+        let mut scanner = Scanner::new_lossless(source);
+        let mut rebuilt = String::new();
 
-        self.tokens.push(Token {
-            line: self.line,
-            lexeme: lexeme.into(),
-            token: token_type,
-            literal,
-        });
-    }
+        for token in scanner.scan_tokens().0 {
+            token.write_source(&mut rebuilt);
+        }
 
-    fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        rebuilt
     }
 
-    fn current_char_test<F>(&self, test: F) -> bool
-    where
-        F: FnOnce(char) -> bool,
-    {
-        let current = self.peek();
+    #[test]
+    fn should_round_trip_lossless_scan() {
+        let source = "var a = \"x ${ b /* c */ } y\"; // d\r\n\n  /* e /* f */ */ 1_0.5e3\t";
 
-        match current {
-            Some(char) => test(char),
-            None => false,
-        }
+        assert_eq!(rebuild_source(source), source);
     }
 
-    fn next_char_test<F>(&self, test: F) -> bool
-    where
-        F: FnOnce(char) -> bool,
-    {
-        let current = self.peek_next();
+    proptest! {
+        #[test]
+        fn lossless_scan_round_trips(source in r#"[a-z0-9_ \t\r\n/*"$\\{}();.+=<>!#é😀]{0,64}"#) {
+            prop_assert_eq!(rebuild_source(&source), source);
+        }
 
-        match current {
-            Some(char) => test(char),
-            None => false,
+        #[test]
+        fn lossless_scan_round_trips_any_text(source in any::<String>()) {
+            prop_assert_eq!(rebuild_source(&source), source);
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{LiteralType, Scanner, ScannerError, Token, TokenType::*};
+        #[test]
+        fn lossless_scan_only_adds_trivia(source in r#"[a-z0-9_ \t\n/*"$\\{}();.+=<>!#]{0,64}"#) {
+            let mut lossless_scanner = Scanner::new_lossless(&source);
+            let mut scanner = Scanner::new(&source);
+
+            let (lossless_tokens, lossless_errors) = lossless_scanner.scan_tokens();
+            let (tokens, errors) = scanner.scan_tokens();
+
+            // Only doc comments are kept outside of lossless mode.
+            let without_trivia: Vec<_> = lossless_tokens
+                .iter()
+                .cloned()
+                .map(|token| Token {
+                    leading_trivia: token
+                        .leading_trivia
+                        .into_iter()
+                        .filter(|trivia| trivia.kind == TriviaKind::DocComment)
+                        .collect(),
+                    trailing_trivia: vec![],
+                    ..token
+                })
+                .collect();
+
+            prop_assert_eq!(&without_trivia, tokens);
+            prop_assert_eq!(lossless_errors.len(), errors.len());
+        }
+    }
 
     #[test]
-    fn should_init_scanner() {
-        let scanner = Scanner::new("Hello, world!");
+    fn should_scan_raw_strings() {
+        let mut scanner =
+            Scanner::new(r###"r"C:\path\no\escapes ${x}" r#"say "hi""# r##"a "# b"##"###);
+
+        let (tokens, errors) = scanner.scan_tokens();
 
+        assert!(errors.is_empty());
         assert_eq!(
-            scanner.source,
-            vec!['H', 'e', 'l', 'l', 'o', ',', ' ', 'w', 'o', 'r', 'l', 'd', '!']
+            tokens
+                .iter()
+                .filter_map(|token| token.literal.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                LiteralType::Str(r"C:\path\no\escapes ${x}".into()),
+                LiteralType::Str(r#"say "hi""#.into()),
+                LiteralType::Str(r##"a "# b"##.into()),
+            ]
         );
+        assert_eq!(tokens[1].lexeme, r###"r#"say "hi""#"###);
+        assert_eq!(tokens[3].token, EOF);
     }
 
     #[test]
-    fn is_at_end_false_on_init() {
-        let scanner = Scanner::new("Hello, world!");
-
-        assert!(!scanner.is_at_end());
-    }
-
-    #[test]
-    fn is_at_end_true_when_at_end() {
-        let mut scanner = Scanner::new("Hello, world!");
+    fn should_still_scan_identifiers_starting_with_r() {
+        let mut scanner = Scanner::new("r rain r_");
 
-        scanner.current = 13;
+        let (tokens, _) = scanner.scan_tokens();
 
-        assert!(scanner.is_at_end());
+        assert!(tokens[..3].iter().all(|token| token.token == Identifier));
     }
 
     #[test]
-    fn is_at_end_false_when_not_at_end() {
-        let mut scanner = Scanner::new("Hello, world!");
+    fn should_error_on_bad_raw_strings() {
+        let mut scanner = Scanner::new(r###"r#"no end" r#x"###);
 
-        scanner.current = 12;
+        let (_, errors) = scanner.scan_tokens();
 
-        assert!(!scanner.is_at_end());
-    }
+        assert!(matches!(
+            errors[0],
+            ScannerError::UnterminatedString {
+                span: Span { start: 0, .. }
+            }
+        ));
 
-    #[test]
-    fn should_return_error_on_unknown_token() {
-        let mut scanner = Scanner::new("#");
+        let mut scanner = Scanner::new("r#x");
 
-        let result = scanner
-            .scan_token()
-            .expect_err("Unexpected successful scan");
+        let (_, errors) = scanner.scan_tokens();
 
         assert!(matches!(
-            result,
-            ScannerError::UnexpectedToken { lexeme: _, line: _ }
+            &errors[0],
+            ScannerError::UnexpectedToken { lexeme, .. } if lexeme == "r#"
         ));
     }
 
     #[test]
-    fn should_return_unexpected_eof() {
-        let mut scanner = Scanner::new("");
-
-        scanner.current = 1;
+    fn should_strip_multiline_string_indentation() {
+        let source =
+            "var q = \"\"\"\n    SELECT *\n      FROM t\n\n    WHERE a = \"b\\n\"\n    \"\"\";\n+";
+        let mut scanner = Scanner::new(source);
 
-        let result = scanner.scan_token();
+        let (tokens, errors) = scanner.scan_tokens();
 
-        assert!(matches!(result, Err(ScannerError::UnexpectedEof)));
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens[3].literal,
+            Some(LiteralType::Str(
+                "SELECT *\n  FROM t\n\nWHERE a = \"b\\n\"".into()
+            ))
+        );
+        assert_eq!(tokens[3].span.line, 1);
+        assert_eq!(tokens[4].token, Semicolon);
+        assert_eq!(tokens[4].span.line, 6);
+        assert_eq!(tokens[5].span.line, 7);
     }
 
     #[test]
-    fn should_add_a_token() {
-        let mut scanner = Scanner::new("=");
+    fn should_keep_first_line_of_multiline_string() {
+        let mut scanner =
+            Scanner::new("\"\"\"Dear ${name},\n        Thanks!\n          Bye\"\"\" \"\"");
 
-        // This would be handled automatically in the scan_tokens method, but for testing purposes we need to set the start and current manually.
-        scanner.current += 1;
-
-        scanner.add_token(LeftParen, None);
+        let (tokens, errors) = scanner.scan_tokens();
 
+        assert!(errors.is_empty());
+        // `"""` strings are raw, the hole is kept as written.
         assert_eq!(
-            scanner.tokens,
-            vec![Token {
-                token: LeftParen,
-                line: 1,
-                literal: None,
-                lexeme: "=".into()
-            }]
-        )
+            tokens[0].literal,
+            Some(LiteralType::Str("Dear ${name},\nThanks!\n  Bye".into()))
+        );
+        assert_eq!(tokens[1].literal, Some(LiteralType::Str("".into())));
     }
 
     #[test]
-    fn should_add_two_char_token() {
-        let mut scanner = Scanner::new("<=");
+    fn should_only_strip_indentation_shared_byte_for_byte() {
+        let mut scanner =
+            Scanner::new("\"\"\"\n\tfoo\n    bar\n\"\"\" \"\"\"\n\t  foo\n\t\tbar\n\tbaz\n\"\"\"");
 
-        let (tokens, _) = scanner.scan_tokens();
+        let (tokens, errors) = scanner.scan_tokens();
 
+        assert!(errors.is_empty());
         assert_eq!(
-            tokens[0],
-            Token {
-                token: LessEqual,
-                line: 1,
-                literal: None,
-                lexeme: "<=".into()
-            }
+            tokens[0].literal,
+            Some(LiteralType::Str("\tfoo\n    bar".into()))
+        );
+        assert_eq!(
+            tokens[1].literal,
+            Some(LiteralType::Str("  foo\n\tbar\nbaz".into()))
         );
     }
 
     #[test]
-    fn should_add_eof_after_scan() {
-        let mut scanner = Scanner::new("");
+    fn should_error_on_unterminated_multiline_string() {
+        let mut scanner = Scanner::new("\"\"\"\n  abc\"\"");
 
-        let (tokens, _) = scanner.scan_tokens();
+        let (_, errors) = scanner.scan_tokens();
 
-        assert_eq!(
-            tokens[0],
-            Token {
-                token: EOF,
-                line: 1,
-                literal: None,
-                lexeme: "\0".into()
+        assert!(matches!(
+            errors[0],
+            ScannerError::UnterminatedString {
+                span: Span {
+                    start: 0,
+                    end: 11,
+                    ..
+                }
             }
-        );
+        ));
+        assert_eq!(scanner.line, 2);
     }
 
     #[test]
-    fn should_strip_comments() {
-        let mut scanner = Scanner::new("// this is a comment");
+    fn should_keep_doc_comments_as_leading_trivia() {
+        let mut scanner = Scanner::new(
+            "1; /// Adds things.\n//// Not docs.\n/// Second line.\nfun // plain\n/**/",
+        );
 
-        let (tokens, _) = scanner.scan_tokens();
+        let (tokens, errors) = scanner.scan_tokens();
 
+        assert!(errors.is_empty());
+        assert!(tokens[1].trailing_trivia.is_empty());
         assert_eq!(
-            tokens[0],
-            Token {
-                token: EOF,
-                line: 1,
-                literal: None,
-                lexeme: "\0".into()
-            }
+            tokens[2].leading_trivia,
+            vec![
+                Trivia {
+                    kind: TriviaKind::DocComment,
+                    text: "/// Adds things.",
+                    span: Span::new(3, 19, 1, 4)
+                },
+                Trivia {
+                    kind: TriviaKind::DocComment,
+                    text: "/// Second line.",
+                    span: Span::new(35, 51, 3, 1)
+                },
+            ]
         );
-        assert!(tokens.len() == 1);
+        assert_eq!(
+            tokens[2].doc_comment(),
+            Some("Adds things.\nSecond line.".into())
+        );
+        assert_eq!(tokens[0].doc_comment(), None);
+        assert!(tokens[3].leading_trivia.is_empty());
     }
 
     #[test]
-    fn should_match_tokens_prior_to_comment() {
-        let mut scanner = Scanner::new("<= // Wow! A less-than-or-equal-to binary operator!");
+    fn should_end_trailing_trivia_at_doc_comment() {
+        let mut scanner = Scanner::new_lossless("a // plain /// still plain\nb /// docs for c\nc");
 
         let (tokens, _) = scanner.scan_tokens();
 
+        assert_eq!(tokens[0].doc_comment(), None);
+        assert_eq!(tokens[1].doc_comment(), None);
         assert_eq!(
-            tokens[0],
-            Token {
-                token: LessEqual,
-                line: 1,
-                literal: None,
-                lexeme: "<=".into()
-            }
+            tokens[1]
+                .trailing_trivia
+                .iter()
+                .map(|trivia| trivia.kind)
+                .collect::<Vec<_>>(),
+            vec![TriviaKind::Whitespace]
         );
-        assert!(tokens.len() == 2); // Includes EOF
+        assert_eq!(tokens[2].doc_comment(), Some("docs for c".into()));
     }
 
-    #[test]
-    fn should_increment_line() {
-        let mut scanner = Scanner::new("(\n()\n+//\r\n");
+    /// The line and column of every token, EOF included.
+    fn positions(source: &str) -> Vec<(usize, usize)> {
+        let mut scanner = Scanner::new(source);
 
-        scanner.scan_tokens();
+        let (tokens, errors) = scanner.scan_tokens();
 
-        assert_eq!(scanner.line, 4);
+        assert!(errors.is_empty());
+
+        tokens
+            .iter()
+            .map(|token| (token.span.line, token.span.column))
+            .collect()
     }
 
     #[test]
-    fn should_ignore_whitespace() {
-        let mut scanner = Scanner::new("\t   \t  \t");
+    fn should_count_lines_for_every_line_ending() {
+        let expected = vec![(1, 1), (2, 1), (3, 3), (4, 1)];
 
-        scanner.scan_tokens();
+        assert_eq!(positions("a\nb\n  c\n"), expected);
+        assert_eq!(positions("a\r\nb\r\n  c\r\n"), expected);
+        assert_eq!(positions("a\rb\r  c\r"), expected);
+        assert_eq!(positions("a\r\nb\r  c\n"), expected);
+    }
 
-        assert_eq!(scanner.tokens.len(), 1); // Includes EOF
+    #[test]
+    fn should_count_lines_in_comments_and_strings_with_crlf() {
+        assert_eq!(
+            positions("// one\r\n/* two\r\nthree\r */\"four\r\nfive\"\r\nsix"),
+            vec![(4, 4), (6, 1), (6, 4)]
+        );
     }
 
     #[test]
-    fn should_scan_string() {
-        let mut scanner = Scanner::new("\"Hello, world!\"");
+    fn should_read_string_line_breaks_as_newlines() {
+        let mut scanner =
+            Scanner::new("\"a\r\nb\rc\" r\"d\r\ne\" \"\"\"\r\n  f\r\n  g\r\n  \"\"\"");
 
-        let (tokens, _) = scanner.scan_tokens();
+        let (tokens, errors) = scanner.scan_tokens();
 
+        assert!(errors.is_empty());
         assert_eq!(
-            tokens[0],
-            Token {
-                token: Str,
-                line: 1,
-                literal: Some(LiteralType::Str("Hello, world!".into())),
-                lexeme: "\"Hello, world!\"".into()
-            }
+            tokens
+                .iter()
+                .filter_map(|token| token.literal.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                LiteralType::Str("a\nb\nc".into()),
+                LiteralType::Str("d\ne".into()),
+                LiteralType::Str("f\ng".into()),
+            ]
         );
     }
 
     #[test]
-    fn should_error_on_unterminated_string() {
-        let mut scanner = Scanner::new("\"Hello, world!");
+    fn should_skip_shebang() {
+        let mut scanner = Scanner::new("#!/usr/bin/env wrig\nprint 1;");
+
+        let (tokens, errors) = scanner.scan_tokens();
+
+        assert!(errors.is_empty());
+        assert_eq!(tokens[0].token, Print);
+        assert_eq!(tokens[0].span, Span::new(20, 25, 2, 1));
+    }
+
+    #[test]
+    fn should_only_skip_shebang_at_start() {
+        let mut scanner = Scanner::new("1\n#!");
 
         let (_, errors) = scanner.scan_tokens();
 
         assert!(matches!(
             errors[0],
-            ScannerError::UnterminatedString { line: 1 }
+            ScannerError::UnexpectedToken {
+                span: Span {
+                    start: 2,
+                    line: 2,
+                    column: 1,
+                    ..
+                },
+                ..
+            }
         ));
     }
 
     #[test]
-    fn should_convert_a_string_to_number() {
-        let mut scanner = Scanner::new("3.14");
-
-        let (tokens, _) = scanner.scan_tokens();
-
+    fn should_skip_byte_order_mark() {
+        assert_eq!(positions("\u{FEFF}a b"), vec![(1, 1), (1, 3), (1, 4)]);
         assert_eq!(
-            tokens[0],
-            Token {
-                token: Number,
-                line: 1,
-                literal: Some(LiteralType::Number(3.14)),
-                lexeme: "3.14".into(),
-            }
+            positions("\u{FEFF}#!/usr/bin/env wrig\r\na"),
+            vec![(2, 1), (2, 2)]
         );
     }
 
     #[test]
-    fn should_convert_string_to_number_int() {
-        let mut scanner = Scanner::new("3");
+    fn should_keep_preamble_as_trivia_in_lossless_mode() {
+        let source = "\u{FEFF}#!/usr/bin/env wrig\r\nprint 1;\r\n";
+        let mut scanner = Scanner::new_lossless(source);
 
-        let (tokens, _) = scanner.scan_tokens();
+        let (tokens, errors) = scanner.scan_tokens();
 
+        assert!(errors.is_empty());
         assert_eq!(
-            tokens[0],
-            Token {
-                token: Number,
-                line: 1,
-                literal: Some(LiteralType::Number(3.0)),
-                lexeme: "3".into(),
+            tokens[0].leading_trivia,
+            vec![
+                Trivia {
+                    kind: TriviaKind::ByteOrderMark,
+                    text: "\u{FEFF}",
+                    span: Span::new(0, 3, 1, 1)
+                },
+                Trivia {
+                    kind: TriviaKind::Shebang,
+                    text: "#!/usr/bin/env wrig",
+                    span: Span::new(3, 22, 1, 1)
+                },
+                Trivia {
+                    kind: TriviaKind::Newline,
+                    text: "\r\n",
+                    span: Span::new(22, 24, 1, 20)
+                },
+            ]
+        );
+        assert_eq!(rebuild_source(source), source);
+    }
+
+    /// Applies `edit` to `source`, then checks re-scanning just around the edit gives the same as a full scan.
+    fn assert_relex_matches_full_scan(source: &str, edit: TextEdit, lossless: bool) {
+        let new_scanner = |source| {
+            if lossless {
+                Scanner::new_lossless(source)
+            } else {
+                Scanner::new(source)
             }
+        };
+
+        let edited = format!(
+            "{}{}{}",
+            &source[..edit.range.start],
+            edit.text,
+            &source[edit.range.end..]
+        );
+
+        let mut old_scanner = new_scanner(source);
+        old_scanner.scan_tokens();
+        let (old_tokens, old_errors) = old_scanner.into_parts();
+
+        let mut full_scanner = new_scanner(&edited);
+        let mut relex_scanner = new_scanner(&edited);
+
+        assert_eq!(
+            relex_scanner.relex(old_tokens, old_errors, &edit),
+            full_scanner.scan_tokens()
         );
     }
 
     #[test]
-    fn should_add_identifier() {
-        let mut scanner = Scanner::new("while");
+    fn should_relex_after_edit() {
+        let source = "var a = 1;\nvar b = \"x ${a} y\";\n// done\nprint a + b;";
+
+        // Inserting a newline moves everything after it down a line.
+        assert_relex_matches_full_scan(
+            source,
+            TextEdit {
+                range: 14..14,
+                text: "\n",
+            },
+            false,
+        );
+        // Opening a string swallows the rest of the line.
+        assert_relex_matches_full_scan(
+            source,
+            TextEdit {
+                range: 19..20,
+                text: "\"",
+            },
+            true,
+        );
+        // Closing the comment early turns the rest of it into tokens.
+        assert_relex_matches_full_scan(
+            source,
+            TextEdit {
+                range: 31..31,
+                text: "\n",
+            },
+            true,
+        );
+    }
 
-        let (tokens, _) = scanner.scan_tokens();
+    #[test]
+    fn should_reuse_tokens_after_edit() {
+        let source = "var long_name = 1;\nprint long_name;";
+        let edit = TextEdit {
+            range: 16..17,
+            text: "22",
+        };
 
-        assert_eq!(
-            tokens[0],
-            Token {
-                token: While,
-                line: 1,
-                literal: None,
-                lexeme: "while".into(),
+        let mut old_scanner = Scanner::new(source);
+        old_scanner.scan_tokens();
+        let (old_tokens, old_errors) = old_scanner.into_parts();
+
+        let mut scanner = Scanner::new("var long_name = 22;\nprint long_name;");
+        let (tokens, errors) = scanner.relex(old_tokens, old_errors, &edit);
+
+        assert!(errors.is_empty());
+        assert_eq!(tokens[3].literal, Some(LiteralType::Number(22.0)));
+        assert_eq!(tokens[4].span, Span::new(18, 19, 1, 19));
+        assert_eq!(tokens[5].span, Span::new(20, 25, 2, 1));
+    }
+
+    #[test]
+    fn should_take_reused_text_from_new_source() {
+        let old_source = String::from("var a = 1;\n/// Doc.\nprint a;");
+        let edited = "var a = 12;\n/// Doc.\nprint a;";
+        let edit = TextEdit {
+            range: 9..9,
+            text: "2",
+        };
+
+        let mut old_scanner = Scanner::new_lossless(&old_source);
+        old_scanner.scan_tokens();
+        let (old_tokens, old_errors) = old_scanner.into_parts();
+
+        let mut scanner = Scanner::new_lossless(edited);
+        let (tokens, _) = scanner.relex(old_tokens, old_errors, &edit);
+
+        drop(old_source);
+
+        let edited_range = edited.as_bytes().as_ptr_range();
+
+        for token in tokens.iter().filter(|token| token.token != EOF) {
+            assert!(edited_range.contains(&token.lexeme.as_ptr()));
+
+            for trivia in token.leading_trivia.iter().chain(&token.trailing_trivia) {
+                assert!(edited_range.contains(&trivia.text.as_ptr()));
             }
-        );
+        }
     }
 
     #[test]
-    fn should_add_identifier_with_underscore() {
-        let mut scanner = Scanner::new("_random");
+    fn should_forget_earlier_scan_when_relexing() {
+        let source = "# 1 + 2;";
+        let edited = "3 + 2;";
+        let edit = TextEdit {
+            range: 0..3,
+            text: "3",
+        };
 
-        let (tokens, _) = scanner.scan_tokens();
+        let mut old_scanner = Scanner::new(source);
+        old_scanner.scan_tokens();
+        let (old_tokens, old_errors) = old_scanner.into_parts();
+
+        let mut scanner = Scanner::new(edited);
+        scanner.scan_tokens();
+        let (tokens, errors) = scanner.relex(old_tokens, old_errors, &edit);
 
+        assert!(errors.is_empty());
         assert_eq!(
-            tokens[0],
-            Token {
-                token: Identifier,
-                line: 1,
-                literal: None,
-                lexeme: "_random".into(),
-            }
+            tokens.iter().map(|token| token.lexeme).collect::<Vec<_>>(),
+            vec!["3", "+", "2", ";", "\0"]
         );
     }
 
-    #[test]
-    fn should_gracefully_continue_scanning_even_of_error() {
-        let mut scanner = Scanner::new("() {} # $ \"hi");
+    /// An edit at character boundaries of a generated source, with text that can open and close strings, comments and
+    /// interpolations.
+    fn source_and_edit() -> impl Strategy<Value = (String, Range<usize>, String)> {
+        let text = r#"[a-z0-9_ \t\r\n/*"$\\{}();.+=<>!#é]"#;
 
-        let (_, errors) = scanner.scan_tokens();
+        (
+            proptest::string::string_regex(&format!("{text}{{0,160}}")).unwrap(),
+            proptest::string::string_regex(&format!("{text}{{0,8}}")).unwrap(),
+            any::<prop::sample::Index>(),
+            any::<prop::sample::Index>(),
+        )
+            .prop_map(|(source, text, start, end)| {
+                let boundaries: Vec<usize> = source
+                    .char_indices()
+                    .map(|(index, _)| index)
+                    .chain([source.len()])
+                    .collect();
+
+                let start = boundaries[start.index(boundaries.len())];
+                let end = boundaries[end.index(boundaries.len())];
+
+                (source, start.min(end)..start.max(end), text)
+            })
+    }
 
-        assert!(errors.len() == 3);
+    proptest! {
+        #[test]
+        fn relex_matches_full_scan((source, range, text) in source_and_edit()) {
+            assert_relex_matches_full_scan(&source, TextEdit { range: range.clone(), text: &text }, false);
+        }
+
+        #[test]
+        fn lossless_relex_matches_full_scan((source, range, text) in source_and_edit()) {
+            assert_relex_matches_full_scan(&source, TextEdit { range, text: &text }, true);
+        }
     }
 }
@@ -1,7 +1,11 @@
 pub use literal_type::*;
+pub use span::*;
 pub use token::*;
 pub use token_type::*;
+pub use trivia::*;
 
 mod literal_type;
+mod span;
 mod token;
 mod token_type;
+mod trivia;
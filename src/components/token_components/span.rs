@@ -0,0 +1,30 @@
+use std::fmt::Display;
+
+/// The location of a token (or error) in the source.
+///
+/// `start` and `end` are byte offsets into the source, with `end` being exclusive.
+/// `line` and `column` are 1-based and point at the first character of the span. Columns count characters, not bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
@@ -1,17 +1,62 @@
 use std::fmt::Display;
 
 use super::LiteralType;
+use super::Span;
 use super::TokenType;
+use super::{Trivia, TriviaKind};
 
+/// A token borrows its lexeme from the source it was scanned from.
 #[derive(Debug, PartialEq, Clone)]
-pub struct Token {
+pub struct Token<'src> {
     pub token: TokenType,
-    pub line: usize,
-    pub lexeme: String,
+    pub lexeme: &'src str,
     pub literal: Option<LiteralType>,
+    pub span: Span,
+    /// Whitespace and comments before the token, including any newlines. Only doc comments unless scanned in lossless mode.
+    pub leading_trivia: Vec<Trivia<'src>>,
+    /// Whitespace and comments after the token, up to the end of its line. Empty unless scanned in lossless mode.
+    pub trailing_trivia: Vec<Trivia<'src>>,
 }
 
-impl Display for Token {
+impl Token<'_> {
+    /// The text of the `///` doc comments before the token, one line per comment, without the slashes.
+    pub fn doc_comment(&self) -> Option<String> {
+        let lines: Vec<&str> = self
+            .leading_trivia
+            .iter()
+            .filter(|trivia| trivia.kind == TriviaKind::DocComment)
+            .map(|trivia| {
+                let text = trivia.text.trim_start_matches("///");
+
+                text.strip_prefix(' ').unwrap_or(text).trim_end()
+            })
+            .collect();
+
+        if lines.is_empty() {
+            return None;
+        }
+
+        Some(lines.join("\n"))
+    }
+
+    /// Appends the token's exact source text, trivia included, to `out`.
+    /// Doing this for every token of a lossless scan gives back the original source.
+    pub fn write_source(&self, out: &mut String) {
+        for trivia in &self.leading_trivia {
+            out.push_str(trivia.text);
+        }
+
+        if self.token != TokenType::EOF {
+            out.push_str(self.lexeme);
+        }
+
+        for trivia in &self.trailing_trivia {
+            out.push_str(trivia.text);
+        }
+    }
+}
+
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.lexeme)
     }
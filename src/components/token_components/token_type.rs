@@ -1,3 +1,4 @@
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenType {
     // Single-character tokens.
@@ -5,15 +6,20 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
     Plus,
+    Question,
     Semicolon,
     Slash,
     Star,
 
     // One or two character tokens.
+    Arrow,
     Bang,
     BangEqual,
     Equal,
@@ -28,9 +34,17 @@ pub enum TokenType {
     Str,
     Number,
 
+    // Interpolated strings, e.g. `"a ${b} c ${d} e"` is scanned as
+    // InterpolationStart(`"a ${`), b, InterpolationSegment(`} c ${`), d, InterpolationEnd(`} e"`).
+    InterpolationStart,
+    InterpolationSegment,
+    InterpolationEnd,
+
     // Keywords.
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -0,0 +1,28 @@
+use super::Span;
+
+/// Source text that isn't part of any token. Only recorded when scanning in lossless mode, apart from doc comments
+/// which are always kept.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia<'src> {
+    pub kind: TriviaKind,
+    pub text: &'src str,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriviaKind {
+    /// Spaces and tabs.
+    Whitespace,
+    /// A single line break, `\n`, `\r\n` or a lone `\r`.
+    Newline,
+    LineComment,
+    /// A `///` comment documenting the declaration that follows it.
+    DocComment,
+    BlockComment,
+    /// Text the scanner reported an error for instead of producing a token.
+    Skipped,
+    /// A UTF-8 byte order mark at the start of the source.
+    ByteOrderMark,
+    /// A `#!` interpreter line at the start of the source, e.g. `#!/usr/bin/env wrig`.
+    Shebang,
+}
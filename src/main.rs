@@ -1,6 +1,4 @@
-use crate::components::Program;
-
-mod components;
+use wrig::components::Program;
 
 fn main() {
     let test_source = String::from("(){}+-; and # for var <= >= \"hey\" random   // comment");